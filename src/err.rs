@@ -17,6 +17,15 @@ pub struct PEErr
     pub message : String,
 }
 
+impl PEErr
+{
+    // Shorthand for the common failure case
+    pub fn failure<S: Into<String>>(message: S) -> PEErr
+    {
+        PEErr { status: ErrState::Failure, message: message.into() }
+    }
+}
+
 impl fmt::Display for ErrState
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
@@ -46,3 +55,11 @@ impl fmt::Debug for PEErr
 }
 
 impl Error for PEErr {}
+
+impl From<std::io::Error> for PEErr
+{
+    fn from(e: std::io::Error) -> PEErr
+    {
+        PEErr::failure(format!("I/O error: {}", e))
+    }
+}
@@ -1,43 +1,220 @@
+use crate::err::*;
 use std::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use std::fmt;
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::str::FromStr;
 
-#[derive(PartialEq)]
-pub enum Integer
-{
-    U8(u8),
-    U16(u16),
-    U32(u32),
-    U64(u64),
-    USize(usize),
-}
+mod bitness;
+mod cache;
+mod cursor;
+mod entropy;
+mod file;
+mod hexdump;
+mod integer;
+#[macro_use]
+mod layout;
+mod list;
+mod ntstring;
+mod patch;
+mod pattern;
+#[cfg(target_os = "linux")]
+mod process;
+mod region;
+mod remote;
+mod strings;
+mod widestring;
+pub use bitness::Bitness;
+pub use cache::{CachedMemory, CacheStats};
+pub use cursor::MemCursor;
+pub use entropy::{ByteHistogram, WindowEntropy, entropy, entropy_windows};
+pub use file::FileMemory;
+pub use hexdump::{HexDump, AddressColumn, parse_hex_dump};
+pub use integer::{Integer, IntWidth, read_integer};
+pub use layout::{NtField, NtStruct, ListEntry, ListEntryOffsets};
+pub use list::{ListEntryIter, ListDirection};
+pub use patch::{Patch, PatchSet, write_mem, write_integer};
+pub use ntstring::{UnicodeString, AnsiString, read_unicode_string, read_ansi_string};
+pub use pattern::Pattern;
+#[cfg(target_os = "linux")]
+pub use process::ProcessMemory;
+pub use strings::{FoundString, StringEncoding, extract_strings, extract_strings_from};
+pub use remote::RemotePtr;
+pub use widestring::{WideString, IgnoreCase, upcase_unit};
+pub use region::{MemoryRegion, Protection, MappedFile, RegionMap, CheckedMemory, parse_proc_maps};
 
 #[macro_export]
 macro_rules! read_null
 {
-    ($addr: expr, $size: ident) => 
+    // Read through a MemorySource, yields a Result
+    // Pass map.guard(LiveMemory::new()) to validate live reads against a RegionMap
+    ($src: expr, $addr: expr, $size: ident) =>
     {
         {
+            let src = &$src;
+            let addr: usize = $addr;
             let mut chunks: Vec<$size> = Vec::new();
             let mut idx = 0;
             let step = std::mem::size_of::<$size>();
+            let mut res = Ok(());
 
             loop
             {
-                let chunk = unsafe { *(($addr + idx) as *const $size) };
+                let mut buf = [0u8; std::mem::size_of::<$size>()];
+                let read = addr.checked_add(idx)
+                               .ok_or_else(|| $crate::err::PEErr::failure(format!("String at {:#x} runs past the end of the address space", addr)))
+                               .and_then(|at| $crate::memory::MemorySource::read_bytes(src, at, &mut buf));
+                if let Err(e) = read
+                {
+                    res = Err(e);
+                    break;
+                }
+
+                let chunk = $size::from_le_bytes(buf);
                 if chunk == 0
                 {
                     break;
                 }
-                
+
                 chunks.push(chunk);
                 idx += step;
             }
 
-            (chunks, idx/step)
+            res.map(|_| (chunks, idx/step))
         }
     };
+
+    // Unchecked read of the current process, must be used inside an unsafe block
+    // Deprecated: read_null!(map.guard(LiveMemory::new()), addr, T) validates the reads against a RegionMap
+    ($addr: expr, $size: ident) =>
+    {
+        $crate::memory::read_null_unchecked::<$size>($addr)
+    };
+}
+
+/// Backend of the unchecked `read_null!(addr, T)`
+///
+/// # Safety
+/// Every element up to the terminator must be readable memory of the current process
+#[doc(hidden)]
+#[deprecated(note = "unchecked, use read_null!(map.guard(LiveMemory::new()), addr, T) to validate the reads against a RegionMap")]
+pub unsafe fn read_null_unchecked<T: Copy + Default + PartialEq>(addr: usize) -> (Vec<T>, usize)
+{
+    let mut chunks: Vec<T> = Vec::new();
+    let step = std::mem::size_of::<T>();
+    let mut idx = 0;
+
+    loop
+    {
+        let chunk = std::ptr::read_unaligned((addr + idx) as *const T);
+        if chunk == T::default()
+        {
+            break;
+        }
+
+        chunks.push(chunk);
+        idx += step;
+    }
+
+    (chunks, idx/step)
+}
+
+// Outcome of a length limited read of a null terminated string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NullRead<T>
+{
+    Terminated(Vec<T>),     // The terminator was found, it is not included
+    Truncated(Vec<T>),      // max_len elements were read without finding a terminator
+}
+
+impl<T> NullRead<T>
+{
+    pub fn is_truncated(&self) -> bool
+    {
+        matches!(self, NullRead::Truncated(_))
+    }
+
+    pub fn data(&self) -> &[T]
+    {
+        match self
+        {
+            NullRead::Terminated(d) | NullRead::Truncated(d) => d,
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<T>
+    {
+        match self
+        {
+            NullRead::Terminated(d) | NullRead::Truncated(d) => d,
+        }
+    }
+}
+
+// Bytes fetched per read when looking for the terminator, slow sources get a few reads per string
+const CSTR_CHUNK: usize = 0x40;
+
+// Fallible counterpart of read_null!(src, addr, u8)
+// Reads at most max_len bytes, a failing read is returned as an error
+pub fn read_cstr_limited<M: MemorySource + ?Sized>(src: &M, addr: usize, max_len: usize) -> Result<NullRead<u8>, PEErr>
+{
+    let mut chars: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; CSTR_CHUNK];
+
+    while chars.len() < max_len
+    {
+        let at = addr.checked_add(chars.len()).ok_or_else(|| overflow(addr))?;
+        let size = CSTR_CHUNK.min(max_len - chars.len());
+
+        // A chunk crossing the end of the readable data is read again byte per byte,
+        // the terminator may be before the first unreadable byte
+        let chunk = match src.read_bytes(at, &mut chunk[..size])
+        {
+            Ok(()) => &chunk[..size],
+            Err(_) =>
+            {
+                chunk[0] = src.read_u8(at)?;
+                &chunk[..1]
+            },
+        };
+
+        if let Some(end) = chunk.iter().position(|&c| c == 0)
+        {
+            chars.extend_from_slice(&chunk[..end]);
+            return Ok(NullRead::Terminated(chars));
+        }
+        chars.extend_from_slice(chunk);
+    }
+
+    Ok(NullRead::Truncated(chars))
 }
 
+// Same as read_cstr_limited, for UTF-16LE strings
+// max_len is a number of u16, not of bytes
+pub fn read_wstr_limited<M: MemorySource + ?Sized>(src: &M, addr: usize, max_len: usize) -> Result<NullRead<u16>, PEErr>
+{
+    let mut chars: Vec<u16> = Vec::new();
+
+    for idx in 0..max_len
+    {
+        let c = src.read_u16(idx.checked_mul(2).and_then(|o| addr.checked_add(o)).ok_or_else(|| overflow(addr))?)?;
+        if c == 0
+        {
+            return Ok(NullRead::Terminated(chars));
+        }
+        chars.push(c);
+    }
+
+    Ok(NullRead::Truncated(chars))
+}
+
+fn overflow(addr: usize) -> PEErr
+{
+    PEErr::failure(format!("String at {:#x} runs past the end of the address space", addr))
+}
+
+/// # Safety
+/// `addr..addr+size` must be readable memory of the current process
+#[deprecated(note = "unchecked, use read_mem_checked to validate the range against a RegionMap")]
 pub unsafe fn read_mem<T:Copy>(addr: usize, size: usize, step: usize) -> MemSlice<T>
 {
     let mut mem: Vec<T> = Vec::new();
@@ -50,22 +227,52 @@ pub unsafe fn read_mem<T:Copy>(addr: usize, size: usize, step: usize) -> MemSlic
         idx += step;
     }
 
-    MemSlice{stub: mem}
+    MemSlice::at(addr, mem)
 }
 
+/// Same as `read_mem`, but through a `MemorySource`
+///
+/// # Safety
+/// `T` must be valid for any bit pattern (plain integers, arrays of integers...)
+pub unsafe fn read_mem_from<T: Copy, M: MemorySource + ?Sized>(src: &M, addr: usize, size: usize, step: usize)
+    -> Result<MemSlice<T>, PEErr>
+{
+    let mut mem: Vec<T> = Vec::new();
+    let mut buf = vec![0u8; std::mem::size_of::<T>()];
+    let mut idx = 0;
+
+    while idx < size
+    {
+        let at = addr.checked_add(idx)
+                     .ok_or_else(|| PEErr::failure(format!("Read at {:#x} + {:#x} overflows the address space", addr, idx)))?;
+        src.read_bytes(at, &mut buf)?;
+        mem.push(std::ptr::read_unaligned(buf.as_ptr() as *const T));
+        idx += step;
+    }
+
+    Ok(MemSlice::at(addr, mem))
+}
+
+/// # Safety
+/// `addr+offset` must point to `size` readable lines of 16 bytes
+#[deprecated(note = "unchecked, use hex_dump_checked to validate the range against a RegionMap")]
 pub unsafe fn hex_dump(addr: usize, offset: usize, size: usize)
 {
-    for i in 0..size
+    print_hex_lines(std::slice::from_raw_parts((addr + offset) as *const u8, size * 0x10));
+}
+
+fn print_hex_lines(bytes: &[u8])
+{
+    for line in bytes.chunks(0x10)
     {
-        let arr: [u8;16] = *((addr + ((i * 0x10) + offset)) as *const [u8;16]);
         let mut chars: Vec<u8> = Vec::new();
 
-        for &var in arr.iter()
+        for &var in line.iter()
         {
             match &var
             {
-                33..=126 => &chars.push(var),
-                _ => &chars.push(46u8),
+                33..=126 => chars.push(var),
+                _ => chars.push(46u8),
             };
             print!("{:02x} ", &var);
         }
@@ -74,6 +281,32 @@ pub unsafe fn hex_dump(addr: usize, offset: usize, size: usize)
     }
 }
 
+/// Checked counterpart of `read_mem`, the range is validated against the region map
+/// so an invalid address becomes an error instead of a crash
+///
+/// # Safety
+/// `T` must be valid for any bit pattern (plain integers, arrays of integers...)
+pub unsafe fn read_mem_checked<T: Copy>(map: &RegionMap, addr: usize, size: usize, step: usize) -> Result<MemSlice<T>, PEErr>
+{
+    read_mem_from(&map.guard(LiveMemory::new()), addr, size, step)
+}
+
+// Checked counterpart of hex_dump, the map must describe the current process (RegionMap::current())
+pub fn hex_dump_checked(map: &RegionMap, addr: usize, offset: usize, size: usize) -> Result<(), PEErr>
+{
+    let start = addr.checked_add(offset)
+                    .ok_or_else(|| PEErr::failure(format!("Dump at {:#x} + {:#x} overflows the address space", addr, offset)))?;
+    let len = size.checked_mul(0x10)
+                  .ok_or_else(|| PEErr::failure(format!("Dump of {:#x} lines is too large", size)))?;
+
+    // Checked before allocating the buffer
+    map.check_read(start, len)?;
+    let bytes = unsafe { map.guard(LiveMemory::new()) }.read_vec(start, len)?;
+    print_hex_lines(&bytes);
+
+    Ok(())
+}
+
 pub fn utf16_to_str(utf: &[u16]) -> String
 {
     decode_utf16(utf.iter().cloned())
@@ -81,41 +314,620 @@ pub fn utf16_to_str(utf: &[u16]) -> String
             .collect()
 }
 
+// =================================================== Memory Sources
+
+// Abstraction over "where the bytes come from"
+// Lets the PE / PEB parsers work on the live process as well as on captured buffers
+pub trait MemorySource
+{
+    // Fill buf with the bytes located at addr
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>;
+
+    fn read_vec(&self, addr: usize, size: usize) -> Result<Vec<u8>, PEErr>
+    {
+        let mut buf = vec![0u8; size];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_u8(&self, addr: usize) -> Result<u8, PEErr>
+    {
+        let mut buf = [0u8; 1];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&self, addr: usize) -> Result<u16, PEErr>
+    {
+        let mut buf = [0u8; 2];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&self, addr: usize) -> Result<u32, PEErr>
+    {
+        let mut buf = [0u8; 4];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&self, addr: usize) -> Result<u64, PEErr>
+    {
+        let mut buf = [0u8; 8];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    // Pointer sized read, using the host pointer width
+    fn read_usize(&self, addr: usize) -> Result<usize, PEErr>
+    {
+        let mut buf = [0u8; std::mem::size_of::<usize>()];
+        self.read_bytes(addr, &mut buf)?;
+        Ok(usize::from_le_bytes(buf))
+    }
+
+    // Pointer sized read, using the pointer width of the target
+    fn read_ptr(&self, addr: usize, bitness: Bitness) -> Result<usize, PEErr>
+    {
+        match bitness
+        {
+            Bitness::Bit32 => Ok(self.read_u32(addr)? as usize),
+            Bitness::Bit64 => usize::try_from(self.read_u64(addr)?)
+                                    .map_err(|_| PEErr::failure(format!("Pointer at {:#x} does not fit the host", addr))),
+        }
+    }
+}
+
+impl<T: MemorySource + ?Sized> MemorySource for &T
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        (**self).read_bytes(addr, buf)
+    }
+}
+
+impl<T: MemorySource + ?Sized> MemorySource for &mut T
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        (**self).read_bytes(addr, buf)
+    }
+}
+
+// Memory sources that can also be written to
+pub trait MemorySourceMut: MemorySource
+{
+    fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), PEErr>;
+}
+
+impl<T: MemorySourceMut + ?Sized> MemorySourceMut for &mut T
+{
+    fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), PEErr>
+    {
+        (**self).write_bytes(addr, data)
+    }
+}
+
+// Memory of the current process, addresses are raw pointers
+// Every address handed to it is dereferenced, hence the unsafe constructor
+#[derive(Clone, Copy, Debug)]
+pub struct LiveMemory
+{
+    _private: (),
+}
+
+impl LiveMemory
+{
+    /// # Safety
+    /// Every address read through the returned source must be readable memory of the current process,
+    /// `map.guard(LiveMemory::new())` validates them against a `RegionMap` of the process
+    pub unsafe fn new() -> LiveMemory
+    {
+        LiveMemory { _private: () }
+    }
+}
+
+impl MemorySource for LiveMemory
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        if addr == 0 || addr.checked_add(buf.len()).is_none()
+        {
+            return Err(PEErr::failure(format!("Invalid read of {:#x} bytes at {:#x}", buf.len(), addr)));
+        }
+
+        unsafe
+        {
+            std::ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), buf.len());
+        }
+
+        Ok(())
+    }
+}
+
+// Bytes held in a buffer, mapped at a virtual base address
+#[derive(Clone)]
+pub struct BufferMemory<B: AsRef<[u8]> = Vec<u8>>
+{
+    pub base_addr: usize,
+    data: B,
+}
+
+impl<B: AsRef<[u8]>> BufferMemory<B>
+{
+    pub fn new(base_addr: usize, data: B) -> BufferMemory<B>
+    {
+        BufferMemory { base_addr, data }
+    }
+
+    pub fn data(&self) -> &[u8]
+    {
+        self.data.as_ref()
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.data().len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.data().is_empty()
+    }
+
+    // Saturates for a buffer reaching the end of the address space
+    pub fn end_addr(&self) -> usize
+    {
+        self.base_addr.saturating_add(self.len())
+    }
+}
+
+impl<B: AsRef<[u8]>> MemorySource for BufferMemory<B>
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        let start = addr.checked_sub(self.base_addr);
+        let end = start.and_then(|s| s.checked_add(buf.len()));
+
+        match (start, end)
+        {
+            (Some(start), Some(end)) if end <= self.len() =>
+            {
+                buf.copy_from_slice(&self.data()[start..end]);
+                Ok(())
+            },
+            _ => Err(PEErr::failure(format!("Read of {:#x} bytes at {:#x} is outside of the buffer [{:#x}..{:#x}]",
+                                            buf.len(), addr, self.base_addr, self.end_addr()))),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> BufferMemory<B>
+{
+    pub fn data_mut(&mut self) -> &mut [u8]
+    {
+        self.data.as_mut()
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> MemorySourceMut for BufferMemory<B>
+{
+    fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), PEErr>
+    {
+        let start = addr.checked_sub(self.base_addr);
+        let end = start.and_then(|s| s.checked_add(data.len()));
+
+        match (start, end)
+        {
+            (Some(start), Some(end)) if end <= self.len() =>
+            {
+                self.data_mut()[start..end].copy_from_slice(data);
+                Ok(())
+            },
+            _ => Err(PEErr::failure(format!("Write of {:#x} bytes at {:#x} is outside of the buffer [{:#x}..{:#x}]",
+                                            data.len(), addr, self.base_addr, self.end_addr()))),
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for BufferMemory<B>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "BufferMemory {{ base_addr: {:#x}, len: {:#x} }}", self.base_addr, self.len())
+    }
+}
+
+
+// =================================================== Typed Memory
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian
+{
+    Little,
+    Big,
+}
+
+// Integer types a MemSlice can be viewed as
+pub trait MemInt: Copy + Default + Eq + fmt::Debug + fmt::LowerHex + fmt::UpperHex
+{
+    const SIZE: usize;
+
+    // bytes must hold exactly SIZE bytes
+    fn from_bytes(bytes: &[u8], endian: Endian) -> Self;
+    fn push_bytes(self, out: &mut Vec<u8>, endian: Endian);
+    fn from_hex(s: &str) -> Option<Self>;
+}
+
+macro_rules! mem_int
+{
+    ($($t: ty),*) =>
+    {
+        $(
+            impl MemInt for $t
+            {
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn from_bytes(bytes: &[u8], endian: Endian) -> Self
+                {
+                    let mut buf = [0u8; std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(bytes);
+
+                    match endian
+                    {
+                        Endian::Little => <$t>::from_le_bytes(buf),
+                        Endian::Big => <$t>::from_be_bytes(buf),
+                    }
+                }
+
+                fn push_bytes(self, out: &mut Vec<u8>, endian: Endian)
+                {
+                    match endian
+                    {
+                        Endian::Little => out.extend_from_slice(&self.to_le_bytes()),
+                        Endian::Big => out.extend_from_slice(&self.to_be_bytes()),
+                    }
+                }
+
+                fn from_hex(s: &str) -> Option<Self>
+                {
+                    <$t>::from_str_radix(s, 16).ok()
+                }
+            }
+        )*
+    };
+}
+
+mem_int!(u8, u16, u32, u64, usize);
 
 // Memory Structure to holds bytes
 // and associated methods
+// Typed view over values read from memory, remembering the address they were read from
+#[derive(Clone, Default)]
 pub struct MemSlice<T>
 {
-    pub stub: Vec<T>,
+    data: Vec<T>,
+    addr: Option<usize>,
+}
+
+impl<T> MemSlice<T>
+{
+    pub fn new(data: Vec<T>) -> MemSlice<T>
+    {
+        MemSlice { data, addr: None }
+    }
+
+    // Values read from addr
+    pub fn at(addr: usize, data: Vec<T>) -> MemSlice<T>
+    {
+        MemSlice { data, addr: Some(addr) }
+    }
+
+    pub fn addr(&self) -> Option<usize>
+    {
+        self.addr
+    }
+
+    pub fn into_vec(self) -> Vec<T>
+    {
+        self.data
+    }
 }
 
-impl fmt::Display for MemSlice<u8> 
+impl<T: Clone> MemSlice<T>
 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result 
+    // Sub slice, its address follows the position inside the source
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Option<MemSlice<T>>
     {
-        write!(f, "[ {}]", self.stub.iter().cloned().map(|x| format!("{:02X} ",x)).collect::<String>())
+        let start = match range.start_bound()
+        {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound()
+        {
+            Bound::Included(&e) => e.checked_add(1)?,
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => self.data.len(),
+        };
+
+        let data = self.data.get(start..end)?.to_vec();
+        let addr = match self.addr
+        {
+            Some(a) => Some(start.checked_mul(std::mem::size_of::<T>()).and_then(|o| a.checked_add(o))?),
+            None => None,
+        };
+
+        Some(MemSlice { data, addr })
     }
 }
 
-impl PartialEq for MemSlice<u8>
+impl<T: MemInt> MemSlice<T>
 {
-    fn eq (&self, other: &Self) -> bool
+    pub fn to_bytes(&self, endian: Endian) -> Vec<u8>
     {
-        if self.stub.len() != other.stub.len()
+        let mut bytes = Vec::with_capacity(self.data.len() * T::SIZE);
+
+        for v in &self.data
         {
-            return false
+            v.push_bytes(&mut bytes, endian);
         }
 
-        for i in 0..self.stub.len()
+        bytes
+    }
+
+    // View the same memory as another integer width
+    // Fails if the size in bytes is not a multiple of the new width
+    pub fn reinterpret<U: MemInt>(&self, endian: Endian) -> Result<MemSlice<U>, PEErr>
+    {
+        let bytes = self.to_bytes(endian);
+
+        if !bytes.len().is_multiple_of(U::SIZE)
+        {
+            return Err(PEErr::failure(format!("Cannot reinterpret {:#x} bytes as {} byte values", bytes.len(), U::SIZE)));
+        }
+
+        Ok(MemSlice { data: bytes.chunks(U::SIZE).map(|c| U::from_bytes(c, endian)).collect(),
+                      addr: self.addr })
+    }
+
+    // Parse whitespace separated hex values ("4c 8b d1", "0x1234 0xabcd")
+    // A token longer than one value is split in consecutive values ("4c8bd1")
+    pub fn from_hex(s: &str) -> Result<MemSlice<T>, PEErr>
+    {
+        let digits = T::SIZE * 2;
+        let mut data = Vec::new();
+
+        for token in s.split_whitespace()
         {
-            if &self.stub[i] != &other.stub[i]
+            let invalid = || PEErr::failure(format!("Invalid hex value '{}'", token));
+
+            let hex = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token);
+            if hex.is_empty() || !hex.is_ascii()
+            {
+                return Err(invalid());
+            }
+
+            if hex.len() <= digits
+            {
+                data.push(T::from_hex(hex).ok_or_else(invalid)?);
+                continue;
+            }
+
+            if !hex.len().is_multiple_of(digits)
+            {
+                return Err(invalid());
+            }
+
+            for i in (0..hex.len()).step_by(digits)
             {
-                return false
+                data.push(T::from_hex(&hex[i..i + digits]).ok_or_else(invalid)?);
             }
         }
 
-        true
+        Ok(MemSlice::new(data))
     }
 }
 
-impl Eq for MemSlice<u8> {}
+impl<T: MemInt> FromStr for MemSlice<T>
+{
+    type Err = PEErr;
+
+    fn from_str(s: &str) -> Result<MemSlice<T>, PEErr>
+    {
+        MemSlice::from_hex(s)
+    }
+}
+
+impl<T> Deref for MemSlice<T>
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T]
+    {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for MemSlice<T>
+{
+    fn deref_mut(&mut self) -> &mut [T]
+    {
+        &mut self.data
+    }
+}
+
+impl<T> AsRef<[T]> for MemSlice<T>
+{
+    fn as_ref(&self) -> &[T]
+    {
+        &self.data
+    }
+}
+
+impl<T> From<Vec<T>> for MemSlice<T>
+{
+    fn from(data: Vec<T>) -> MemSlice<T>
+    {
+        MemSlice::new(data)
+    }
+}
+
+impl<T> From<MemSlice<T>> for Vec<T>
+{
+    fn from(slice: MemSlice<T>) -> Vec<T>
+    {
+        slice.data
+    }
+}
+
+impl<T> FromIterator<T> for MemSlice<T>
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> MemSlice<T>
+    {
+        MemSlice::new(iter.into_iter().collect())
+    }
+}
+
+impl<T: MemInt> fmt::Display for MemSlice<T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "[ ")?;
+        for v in &self.data
+        {
+            write!(f, "{:0w$X} ", v, w = T::SIZE * 2)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MemSlice<T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self.addr
+        {
+            Some(addr) => write!(f, "MemSlice@{:#x}{:?}", addr, self.data),
+            None => write!(f, "MemSlice{:?}", self.data),
+        }
+    }
+}
+
+// The address is not part of the comparison, only the values are
+impl<T: PartialEq> PartialEq for MemSlice<T>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.data == other.data
+    }
+}
+
+impl<T: Eq> Eq for MemSlice<T> {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn buffer_reads_are_bounded()
+    {
+        let mem = BufferMemory::new(0x1000, vec![1u8, 2, 3, 4]);
+
+        assert_eq!(mem.read_u32(0x1000).unwrap(), 0x04030201);
+        assert_eq!(mem.read_u8(0x1003).unwrap(), 4);
+        assert!(mem.read_u8(0x1004).is_err());
+        assert!(mem.read_u16(0x1003).is_err());
+        assert!(mem.read_u8(0xfff).is_err());
+    }
+
+    #[test]
+    fn buffer_at_the_top_of_the_address_space()
+    {
+        let mut mem = BufferMemory::new(usize::MAX - 1, vec![0u8; 4]);
+
+        assert_eq!(mem.end_addr(), usize::MAX);
+        assert!(mem.read_u8(0).is_err());
+        assert_eq!(mem.read_u8(usize::MAX).unwrap(), 0);
+        assert!(mem.write_bytes(0, &[1]).is_err());
+        assert!(format!("{:?}", mem).contains("len: 0x4"));
+    }
+
+    #[test]
+    fn pointers_follow_the_bitness()
+    {
+        let mem = BufferMemory::new(0, (1u8..=8).collect::<Vec<u8>>());
+
+        assert_eq!(mem.read_ptr(0, Bitness::Bit32).unwrap(), 0x04030201);
+        assert_eq!(mem.read_ptr(0, Bitness::Bit64).unwrap() as u64, 0x0807060504030201);
+        assert!(mem.read_ptr(4, Bitness::Bit64).is_err());
+    }
+
+    #[test]
+    fn limited_cstr_reads()
+    {
+        let mut data = vec![b'a'; 0x100];
+        data[0x90] = 0;
+        data[0xff] = 0;
+        let mem = BufferMemory::new(0x1000, data);
+
+        // Terminator past the first chunks, and exactly at max_len
+        assert_eq!(read_cstr_limited(&mem, 0x1000, 0x100).unwrap(), NullRead::Terminated(vec![b'a'; 0x90]));
+        assert_eq!(read_cstr_limited(&mem, 0x1000, 0x90).unwrap(), NullRead::Truncated(vec![b'a'; 0x90]));
+        assert_eq!(read_cstr_limited(&mem, 0x1000, 0x91).unwrap().data().len(), 0x90);
+        assert!(read_cstr_limited(&mem, 0x1000, 0).unwrap().is_truncated());
+
+        // Last byte of the buffer, the chunk read fails but the terminator is found
+        assert_eq!(read_cstr_limited(&mem, 0x10f0, 0x100).unwrap(), NullRead::Terminated(vec![b'a'; 0xf]));
+        assert_eq!(read_cstr_limited(&mem, 0x10ff, 0x100).unwrap(), NullRead::Terminated(vec![]));
+
+        // No terminator before the end of the readable data
+        let mem = BufferMemory::new(0x1000, vec![b'a'; 0x50]);
+        assert!(read_cstr_limited(&mem, 0x1000, 0x100).is_err());
+        assert!(read_cstr_limited(&mem, 0x1000, 0x50).unwrap().is_truncated());
+
+        let mem = BufferMemory::new(usize::MAX - 3, vec![b'a'; 4]);
+        assert!(read_cstr_limited(&mem, usize::MAX - 3, 0x100).is_err());
+    }
+
+    #[test]
+    fn sub_slices_track_their_address()
+    {
+        let words = MemSlice::at(0x1000, vec![1u32, 2, 3, 4]);
+
+        let s = words.slice(1..3).unwrap();
+        assert_eq!(*s, [2, 3]);
+        assert_eq!(s.addr(), Some(0x1004));
+        assert_eq!(words.slice(..=3).unwrap().len(), 4);
+        assert!(words.slice(2..5).is_none());
+        assert!(words.slice(..=usize::MAX).is_none());
+        assert_eq!(MemSlice::new(vec![1u8, 2]).slice(1..).unwrap().addr(), None);
+
+        // Near the top of the address space the address cannot be computed
+        assert!(MemSlice::at(usize::MAX, vec![1u8, 2, 3]).slice(1..).is_none());
+        assert_eq!(MemSlice::at(usize::MAX, vec![1u8, 2, 3]).slice(0..).unwrap().addr(), Some(usize::MAX));
+        assert!(MemSlice::at(usize::MAX - 4, vec![1u32, 2, 3]).slice(2..).is_none());
+    }
+
+    #[test]
+    fn slices_convert_between_widths()
+    {
+        let bytes: MemSlice<u8> = "4c 8b d1 b8".parse().unwrap();
+        assert_eq!(bytes.to_string(), "[ 4C 8B D1 B8 ]");
+
+        let dword = bytes.reinterpret::<u32>(Endian::Little).unwrap();
+        assert_eq!(*dword, [0xb8d18b4c]);
+        assert_eq!(dword.to_bytes(Endian::Big), [0xb8, 0xd1, 0x8b, 0x4c]);
+        assert!(bytes.reinterpret::<u64>(Endian::Little).is_err());
+
+        assert_eq!(*"0x1234 abcd5678".parse::<MemSlice<u16>>().unwrap(), [0x1234, 0xabcd, 0x5678]);
+        assert!("123".parse::<MemSlice<u8>>().is_err());
+        assert!("zz".parse::<MemSlice<u8>>().is_err());
+
+        // Values only, the address is not compared
+        assert_eq!(MemSlice::at(0x1000, vec![1u8]), MemSlice::new(vec![1u8]));
+    }
+}
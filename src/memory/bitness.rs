@@ -0,0 +1,74 @@
+use crate::err::*;
+use super::IntWidth;
+use std::fmt;
+
+// Pointer width of the target being read, independent of the host
+// e.g. a WoW64 PEB or a PE32 image read from a 64-bit host are Bit32
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bitness
+{
+    Bit32,
+    Bit64,
+}
+
+impl Bitness
+{
+    // Pointer width of the current process
+    pub fn host() -> Bitness
+    {
+        if cfg!(target_pointer_width = "32") { Bitness::Bit32 } else { Bitness::Bit64 }
+    }
+
+    pub fn from_ptr_size(size: usize) -> Result<Bitness, PEErr>
+    {
+        match size
+        {
+            4 => Ok(Bitness::Bit32),
+            8 => Ok(Bitness::Bit64),
+            _ => Err(PEErr::failure(format!("Unsupported pointer size: {}", size))),
+        }
+    }
+
+    pub fn ptr_size(self) -> usize
+    {
+        match self
+        {
+            Bitness::Bit32 => 4,
+            Bitness::Bit64 => 8,
+        }
+    }
+
+    // Width of a pointer, to be used with read_integer
+    pub fn width(self) -> IntWidth
+    {
+        match self
+        {
+            Bitness::Bit32 => IntWidth::U32,
+            Bitness::Bit64 => IntWidth::U64,
+        }
+    }
+}
+
+impl Default for Bitness
+{
+    fn default() -> Self
+    {
+        Bitness::host()
+    }
+}
+
+impl From<Bitness> for IntWidth
+{
+    fn from(bitness: Bitness) -> IntWidth
+    {
+        bitness.width()
+    }
+}
+
+impl fmt::Display for Bitness
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}-bit", self.ptr_size() * 8)
+    }
+}
@@ -0,0 +1,268 @@
+use crate::err::*;
+use super::{MemorySource, MemorySourceMut};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const DEFAULT_PAGE_SIZE: usize = 0x1000;
+const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats
+{
+    pub hits: u64,
+    pub misses: u64,    // Page fetched from the inner source
+    pub bypass: u64,    // Read served directly by the inner source (page not fully readable)
+}
+
+struct CachedPage
+{
+    data: Vec<u8>,
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct CacheState
+{
+    pages: HashMap<usize, CachedPage>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+// Caching wrapper for slow sources (remote process, file...)
+// Whole pages are fetched and kept in a LRU, so that small sequential reads cost one fetch per page
+pub struct CachedMemory<M: MemorySource>
+{
+    inner: M,
+    page_size: usize,
+    capacity: usize,
+    state: RefCell<CacheState>,
+}
+
+impl<M: MemorySource> CachedMemory<M>
+{
+    pub fn new(inner: M) -> CachedMemory<M>
+    {
+        CachedMemory::with_config(inner, DEFAULT_PAGE_SIZE, DEFAULT_CAPACITY)
+    }
+
+    // page_size must be a power of two, capacity is a number of pages
+    pub fn with_config(inner: M, page_size: usize, capacity: usize) -> CachedMemory<M>
+    {
+        let page_size = page_size.max(1).next_power_of_two();
+
+        CachedMemory { inner, page_size, capacity: capacity.max(1), state: RefCell::new(CacheState::default()) }
+    }
+
+    pub fn inner(&self) -> &M
+    {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> M
+    {
+        self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats
+    {
+        self.state.borrow().stats
+    }
+
+    // Drop every cached page, to be called when the target memory may have changed
+    pub fn invalidate(&self)
+    {
+        self.state.borrow_mut().pages.clear();
+    }
+
+    // Drop the cached pages overlapping [addr..addr+size]
+    pub fn invalidate_range(&self, addr: usize, size: usize)
+    {
+        let first = addr & !(self.page_size - 1);
+        let end = addr.saturating_add(size);
+
+        self.state.borrow_mut().pages.retain(|&page, _| page < first || page >= end);
+    }
+
+    // Copy the part of page overlapping [addr..addr+buf.len()] into buf
+    // Returns false if the page cannot be fetched as a whole
+    fn read_page(&self, page: usize, addr: usize, buf: &mut [u8]) -> bool
+    {
+        let mut state = self.state.borrow_mut();
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some(p) = state.pages.get_mut(&page)
+        {
+            p.last_use = tick;
+            buf.copy_from_slice(&p.data[addr - page..addr - page + buf.len()]);
+            state.stats.hits += 1;
+            return true;
+        }
+
+        let mut data = vec![0u8; self.page_size];
+        if self.inner.read_bytes(page, &mut data).is_err()
+        {
+            return false;
+        }
+
+        buf.copy_from_slice(&data[addr - page..addr - page + buf.len()]);
+        state.stats.misses += 1;
+
+        if state.pages.len() >= self.capacity
+        {
+            let lru = state.pages.iter().min_by_key(|(_, p)| p.last_use).map(|(&k, _)| k);
+            if let Some(lru) = lru
+            {
+                state.pages.remove(&lru);
+            }
+        }
+
+        state.pages.insert(page, CachedPage { data, last_use: tick });
+        true
+    }
+}
+
+impl<M: MemorySource> MemorySource for CachedMemory<M>
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        let mut done = 0;
+
+        while done < buf.len()
+        {
+            let cur = addr.checked_add(done)
+                          .ok_or_else(|| PEErr::failure(format!("Read of {:#x} bytes at {:#x} overflows", buf.len(), addr)))?;
+            let page = cur & !(self.page_size - 1);
+            let len = (self.page_size - (cur - page)).min(buf.len() - done);
+
+            // Pages that cannot be read whole (end of a buffer / mapping) are read directly
+            if !self.read_page(page, cur, &mut buf[done..done + len])
+            {
+                self.inner.read_bytes(cur, &mut buf[done..done + len])?;
+                self.state.borrow_mut().stats.bypass += 1;
+            }
+
+            done += len;
+        }
+
+        Ok(())
+    }
+}
+
+// Write-through, the pages that were written to are invalidated
+impl<M: MemorySourceMut> MemorySourceMut for CachedMemory<M>
+{
+    fn write_bytes(&mut self, addr: usize, data: &[u8]) -> Result<(), PEErr>
+    {
+        self.invalidate_range(addr, data.len());
+        self.inner.write_bytes(addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    fn buffer(size: usize) -> BufferMemory
+    {
+        BufferMemory::new(0x10000, (0..size).map(|i| (i / 0x100) as u8 ^ i as u8).collect())
+    }
+
+    fn stats(hits: u64, misses: u64, bypass: u64) -> CacheStats
+    {
+        CacheStats { hits, misses, bypass }
+    }
+
+    #[test]
+    fn small_reads_hit_the_cached_page()
+    {
+        let mem = buffer(0x3000);
+        let cache = CachedMemory::new(&mem);
+
+        for addr in (0x10000..0x10100).step_by(4)
+        {
+            assert_eq!(cache.read_u32(addr).unwrap(), mem.read_u32(addr).unwrap());
+        }
+        assert_eq!(cache.stats(), stats(63, 1, 0));
+
+        // Crossing a page boundary costs one fetch per page
+        assert_eq!(cache.read_vec(0x10ff0, 0x20).unwrap(), mem.read_vec(0x10ff0, 0x20).unwrap());
+        assert_eq!(cache.stats(), stats(64, 2, 0));
+    }
+
+    #[test]
+    fn least_recently_used_page_is_evicted()
+    {
+        let mem = buffer(0x4000);
+        let cache = CachedMemory::with_config(&mem, 0x1000, 2);
+
+        cache.read_u8(0x10000).unwrap();
+        cache.read_u8(0x11000).unwrap();
+        cache.read_u8(0x10000).unwrap();
+        assert_eq!(cache.stats(), stats(1, 2, 0));
+
+        // 0x11000 is the oldest one
+        cache.read_u8(0x12000).unwrap();
+        cache.read_u8(0x10000).unwrap();
+        assert_eq!(cache.stats(), stats(2, 3, 0));
+        cache.read_u8(0x11000).unwrap();
+        assert_eq!(cache.stats(), stats(2, 4, 0));
+    }
+
+    #[test]
+    fn invalidated_pages_are_fetched_again()
+    {
+        let mem = buffer(0x3000);
+        let cache = CachedMemory::new(&mem);
+
+        cache.read_u8(0x10000).unwrap();
+        cache.read_u8(0x11000).unwrap();
+
+        cache.invalidate_range(0x10ffc, 2);
+        cache.read_u8(0x11000).unwrap();
+        cache.read_u8(0x10000).unwrap();
+        assert_eq!(cache.stats(), stats(1, 3, 0));
+
+        cache.invalidate_range(0x10fff, 2);
+        cache.read_u8(0x10000).unwrap();
+        cache.read_u8(0x11000).unwrap();
+        assert_eq!(cache.stats(), stats(1, 5, 0));
+
+        cache.invalidate();
+        cache.read_u8(0x10000).unwrap();
+        assert_eq!(cache.stats(), stats(1, 6, 0));
+    }
+
+    #[test]
+    fn writes_go_through_and_invalidate()
+    {
+        let mut cache = CachedMemory::new(buffer(0x2000));
+
+        cache.read_u32(0x10100).unwrap();
+        cache.write_bytes(0x10100, &[0xaa, 0xbb]).unwrap();
+
+        assert_eq!(cache.read_u16(0x10100).unwrap(), 0xbbaa);
+        assert_eq!(cache.inner().read_u16(0x10100).unwrap(), 0xbbaa);
+        assert_eq!(cache.stats(), stats(0, 2, 0));
+    }
+
+    #[test]
+    fn partial_pages_bypass_the_cache()
+    {
+        // The last page is only half backed by the buffer
+        let mem = buffer(0x1800);
+        let cache = CachedMemory::new(&mem);
+
+        assert_eq!(cache.read_vec(0x10ff8, 0x10).unwrap(), mem.read_vec(0x10ff8, 0x10).unwrap());
+        assert_eq!(cache.stats(), stats(0, 1, 1));
+        assert!(cache.read_u8(0x11800).is_err());
+
+        // Top of the address space
+        let mem = BufferMemory::new(usize::MAX - 0xf, vec![0x42; 0x10]);
+        let cache = CachedMemory::new(&mem);
+        assert_eq!(cache.read_u8(usize::MAX).unwrap(), 0x42);
+        assert!(cache.read_u16(usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::err::*;
+use super::{MemorySource, Bitness, NtField, NullRead, Integer, IntWidth, read_cstr_limited, read_wstr_limited};
+
+// Bounds-checked reader over a window [start..start+len] of a memory source
+// Every read past the window returns an error instead of touching the memory
+pub struct MemCursor<'a, M: MemorySource + ?Sized>
+{
+    src: &'a M,
+    start: usize,   // Absolute address of the window
+    len: usize,     // Size of the window
+    pos: usize,     // Current position, relative to start
+}
+
+impl<M: MemorySource + ?Sized> Clone for MemCursor<'_, M>
+{
+    fn clone(&self) -> Self
+    {
+        MemCursor { src: self.src, start: self.start, len: self.len, pos: self.pos }
+    }
+}
+
+impl<'a, M: MemorySource + ?Sized> MemCursor<'a, M>
+{
+    pub fn new(src: &'a M, start: usize, len: usize) -> MemCursor<'a, M>
+    {
+        // Clamp the window so that start + len never overflows
+        let len = len.min(usize::MAX - start);
+        MemCursor { src, start, len, pos: 0 }
+    }
+
+    pub fn start(&self) -> usize
+    {
+        self.start
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    pub fn pos(&self) -> usize
+    {
+        self.pos
+    }
+
+    // Absolute address of the current position
+    pub fn addr(&self) -> usize
+    {
+        self.start + self.pos
+    }
+
+    pub fn remaining(&self) -> usize
+    {
+        self.len - self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) -> Result<&mut Self, PEErr>
+    {
+        if pos > self.len
+        {
+            return Err(self.out_of_range(pos, 0));
+        }
+
+        self.pos = pos;
+        Ok(self)
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<&mut Self, PEErr>
+    {
+        match self.pos.checked_add(count)
+        {
+            Some(pos) => self.seek(pos),
+            None => Err(self.out_of_range(self.pos, count)),
+        }
+    }
+
+    // New cursor over [offset..offset+len] of the current window
+    pub fn sub(&self, offset: usize, len: usize) -> Result<MemCursor<'a, M>, PEErr>
+    {
+        match offset.checked_add(len)
+        {
+            Some(end) if end <= self.len => Ok(MemCursor { src: self.src, start: self.start + offset, len, pos: 0 }),
+            _ => Err(self.out_of_range(offset, len)),
+        }
+    }
+
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        if buf.len() > self.remaining()
+        {
+            return Err(self.out_of_range(self.pos, buf.len()));
+        }
+
+        self.src.read_bytes(self.addr(), buf)?;
+        self.pos += buf.len();
+
+        Ok(())
+    }
+
+    pub fn read_vec(&mut self, size: usize) -> Result<Vec<u8>, PEErr>
+    {
+        // Sizes often come from the target, they must not reach the allocator unchecked
+        if size > self.remaining()
+        {
+            return Err(self.out_of_range(self.pos, size));
+        }
+
+        let mut buf = vec![0u8; size];
+        self.read_bytes(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, PEErr>
+    {
+        let mut buf = [0u8; 1];
+        self.read_bytes(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, PEErr>
+    {
+        let mut buf = [0u8; 2];
+        self.read_bytes(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, PEErr>
+    {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, PEErr>
+    {
+        let mut buf = [0u8; 8];
+        self.read_bytes(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, PEErr>
+    {
+        let mut buf = [0u8; std::mem::size_of::<usize>()];
+        self.read_bytes(&mut buf)?;
+        Ok(usize::from_le_bytes(buf))
+    }
+
+    // Pointer of the target width
+    pub fn read_ptr(&mut self, bitness: Bitness) -> Result<usize, PEErr>
+    {
+        if bitness.ptr_size() > self.remaining()
+        {
+            return Err(self.out_of_range(self.pos, bitness.ptr_size()));
+        }
+
+        let ptr = self.src.read_ptr(self.addr(), bitness)?;
+        self.pos += bitness.ptr_size();
+
+        Ok(ptr)
+    }
+
+    pub fn read_integer(&mut self, width: IntWidth) -> Result<Integer, PEErr>
+    {
+        let mut buf = [0u8; 8];
+        self.read_bytes(&mut buf[..width.size()])?;
+        Integer::from_le_bytes(&buf[..width.size()], width)
+    }
+
+    // Read an nt_struct! (or any NtField) laid out for the given target
+    pub fn read_struct<T: NtField>(&mut self, bitness: Bitness) -> Result<T, PEErr>
+    {
+        let size = T::size(bitness);
+        if size > self.remaining()
+        {
+            return Err(self.out_of_range(self.pos, size));
+        }
+
+        let value = T::read_field(self.src, self.addr(), bitness)?;
+        self.pos += size;
+
+        Ok(value)
+    }
+
+    // Read a null terminated string, the terminator is consumed but not returned
+    // Fails if the window ends before the terminator
+    pub fn read_cstr(&mut self) -> Result<Vec<u8>, PEErr>
+    {
+        match read_cstr_limited(self.src, self.addr(), self.remaining())?
+        {
+            NullRead::Terminated(chars) =>
+            {
+                self.pos += chars.len() + 1;
+                Ok(chars)
+            },
+            NullRead::Truncated(_) => Err(self.unterminated(self.pos)),
+        }
+    }
+
+    // Same as read_cstr, for null terminated UTF-16LE
+    pub fn read_utf16z(&mut self) -> Result<Vec<u16>, PEErr>
+    {
+        match read_wstr_limited(self.src, self.addr(), self.remaining() / 2)?
+        {
+            NullRead::Terminated(chars) =>
+            {
+                self.pos += (chars.len() + 1) * 2;
+                Ok(chars)
+            },
+            NullRead::Truncated(_) => Err(self.unterminated(self.pos)),
+        }
+    }
+
+    fn out_of_range(&self, pos: usize, size: usize) -> PEErr
+    {
+        PEErr::failure(format!("Access of {:#x} bytes at offset {:#x} is outside of the window [{:#x}..{:#x}]",
+                               size, pos, self.start, self.start + self.len))
+    }
+
+    fn unterminated(&self, pos: usize) -> PEErr
+    {
+        PEErr::failure(format!("Unterminated string at {:#x}", self.start + pos))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    fn buffer() -> BufferMemory
+    {
+        BufferMemory::new(0x1000, (0u8..0x40).collect())
+    }
+
+    #[test]
+    fn reads_stay_inside_the_window()
+    {
+        let mem = buffer();
+        let mut c = MemCursor::new(&mem, 0x1010, 8);
+
+        assert_eq!(c.read_u32().unwrap(), 0x13121110);
+        assert_eq!(c.read_u16().unwrap(), 0x1514);
+        assert_eq!(c.remaining(), 2);
+
+        // A read crossing the end fails without moving the cursor
+        assert!(c.read_u32().is_err());
+        assert_eq!(c.pos(), 6);
+        assert_eq!(c.read_u16().unwrap(), 0x1716);
+        assert!(c.read_u8().is_err());
+    }
+
+    #[test]
+    fn seek_and_skip_are_bounded()
+    {
+        let mem = buffer();
+        let mut c = MemCursor::new(&mem, 0x1000, 0x10);
+
+        assert!(c.seek(0x10).is_ok());
+        assert!(c.seek(0x11).is_err());
+        assert_eq!(c.pos(), 0x10);
+
+        c.seek(0).unwrap();
+        assert!(c.skip(usize::MAX).is_err());
+        assert_eq!(c.skip(4).unwrap().read_u8().unwrap(), 4);
+    }
+
+    #[test]
+    fn sub_windows_are_bounded()
+    {
+        let mem = buffer();
+        let c = MemCursor::new(&mem, 0x1000, 0x20);
+
+        let mut s = c.sub(0x1c, 4).unwrap();
+        assert_eq!(s.start(), 0x101c);
+        assert_eq!(s.read_u32().unwrap(), 0x1f1e1d1c);
+        assert!(s.read_u8().is_err());
+
+        assert!(c.sub(0x1c, 5).is_err());
+        assert!(c.sub(usize::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn oversized_vec_reads_fail_before_allocating()
+    {
+        let mem = buffer();
+        let mut c = MemCursor::new(&mem, 0x1000, 0x20);
+
+        // Would abort on the allocation if the size was not checked first
+        assert!(c.read_vec(usize::MAX).is_err());
+        assert!(c.read_vec(0x21).is_err());
+        assert_eq!(c.pos(), 0);
+        assert_eq!(c.read_vec(0x20).unwrap().len(), 0x20);
+    }
+
+    #[test]
+    fn window_is_clamped_to_the_address_space()
+    {
+        let mem = buffer();
+        let c = MemCursor::new(&mem, usize::MAX - 4, 0x100);
+
+        assert_eq!(c.len(), 4);
+    }
+
+    #[test]
+    fn window_larger_than_the_source_fails_on_read()
+    {
+        let mem = buffer();
+        let mut c = MemCursor::new(&mem, 0x1038, 0x10);
+
+        assert!(c.read_u64().is_ok());
+        assert!(c.read_u8().is_err());
+    }
+
+    #[test]
+    fn pointers_follow_the_bitness()
+    {
+        let mem = buffer();
+        let mut c = MemCursor::new(&mem, 0x1000, 0x10);
+
+        assert_eq!(c.read_ptr(Bitness::Bit32).unwrap(), 0x03020100);
+        assert_eq!(c.pos(), 4);
+        assert_eq!(c.read_ptr(Bitness::Bit64).unwrap() as u64, 0x0b0a090807060504);
+        assert!(c.read_ptr(Bitness::Bit64).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::err::*;
+use super::{MemorySource, MemSlice};
+use std::fmt;
+
+// Number of occurrences of every byte value
+#[derive(Clone, PartialEq, Eq)]
+pub struct ByteHistogram
+{
+    counts: [u64; 256],
+    total: u64,
+}
+
+impl ByteHistogram
+{
+    pub fn new() -> ByteHistogram
+    {
+        ByteHistogram { counts: [0; 256], total: 0 }
+    }
+
+    pub fn from_bytes(data: &[u8]) -> ByteHistogram
+    {
+        let mut h = ByteHistogram::new();
+        h.add(data);
+        h
+    }
+
+    pub fn add(&mut self, data: &[u8])
+    {
+        for &b in data
+        {
+            self.counts[b as usize] += 1;
+        }
+        self.total += data.len() as u64;
+    }
+
+    pub fn count(&self, byte: u8) -> u64
+    {
+        self.counts[byte as usize]
+    }
+
+    pub fn counts(&self) -> &[u64; 256]
+    {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64
+    {
+        self.total
+    }
+
+    // Share of the data made of the given byte, in [0..1]
+    pub fn frequency(&self, byte: u8) -> f64
+    {
+        if self.total == 0
+        {
+            return 0.0;
+        }
+
+        self.count(byte) as f64 / self.total as f64
+    }
+
+    // Number of distinct byte values seen
+    pub fn distinct(&self) -> usize
+    {
+        self.counts.iter().filter(|&&c| c != 0).count()
+    }
+
+    // The n most frequent bytes with their count, most frequent first
+    pub fn most_common(&self, n: usize) -> Vec<(u8, u64)>
+    {
+        let mut bytes: Vec<(u8, u64)> = (0..=255u8).map(|b| (b, self.count(b)))
+                                                   .filter(|&(_, c)| c != 0)
+                                                   .collect();
+
+        bytes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        bytes.truncate(n);
+        bytes
+    }
+
+    // Shannon entropy in bits per byte, from 0 (constant) to 8 (uniform)
+    pub fn entropy(&self) -> f64
+    {
+        if self.total == 0
+        {
+            return 0.0;
+        }
+
+        let total = self.total as f64;
+
+        self.counts.iter()
+                   .filter(|&&c| c != 0)
+                   .map(|&c| { let p = c as f64 / total; p * (1.0 / p).log2() })
+                   .sum()
+    }
+}
+
+impl Default for ByteHistogram
+{
+    fn default() -> Self
+    {
+        ByteHistogram::new()
+    }
+}
+
+impl fmt::Debug for ByteHistogram
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "ByteHistogram {{ total: {}, distinct: {}, entropy: {:.3} }}", self.total, self.distinct(), self.entropy())
+    }
+}
+
+// Shannon entropy of the data in bits per byte
+pub fn entropy(data: &[u8]) -> f64
+{
+    ByteHistogram::from_bytes(data).entropy()
+}
+
+impl MemSlice<u8>
+{
+    pub fn histogram(&self) -> ByteHistogram
+    {
+        ByteHistogram::from_bytes(self)
+    }
+
+    pub fn entropy(&self) -> f64
+    {
+        entropy(self)
+    }
+}
+
+// Entropy of one window of a memory range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowEntropy
+{
+    pub addr: usize,
+    pub size: usize,
+    pub entropy: f64,
+}
+
+// Entropy of [addr..addr+size] of a memory source, computed over windows of `window` bytes every `step` bytes
+// The last window is shortened to end with the range
+pub fn entropy_windows<M: MemorySource + ?Sized>(src: &M, addr: usize, size: usize, window: usize, step: usize)
+    -> Result<Vec<WindowEntropy>, PEErr>
+{
+    if window == 0 || step == 0
+    {
+        return Err(PEErr::failure("Entropy window and step must not be zero"));
+    }
+
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while offset < size
+    {
+        let len = window.min(size - offset);
+        let data = src.read_vec(addr + offset, len)?;
+
+        found.push(WindowEntropy { addr: addr + offset, size: len, entropy: entropy(&data) });
+
+        if offset + len == size
+        {
+            break;
+        }
+        offset += step;
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    fn close(a: f64, b: f64) -> bool
+    {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn histogram_counts()
+    {
+        let mut h = ByteHistogram::from_bytes(b"aabbbc");
+        h.add(b"c");
+
+        assert_eq!(h.total(), 7);
+        assert_eq!(h.count(b'b'), 3);
+        assert_eq!(h.distinct(), 3);
+        assert!(close(h.frequency(b'a'), 2.0 / 7.0));
+        assert_eq!(h.most_common(2), [(b'b', 3), (b'a', 2)]);
+        assert_eq!(h.most_common(10).len(), 3);
+
+        assert_eq!(ByteHistogram::new().frequency(0), 0.0);
+        assert_eq!(ByteHistogram::default(), ByteHistogram::new());
+    }
+
+    #[test]
+    fn entropy_bounds()
+    {
+        let uniform: Vec<u8> = (0..=255u8).collect();
+
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[0x90; 0x100]), 0.0);
+        assert!(close(entropy(b"abab"), 1.0));
+        assert!(close(entropy(b"abcd"), 2.0));
+        assert!(close(entropy(&uniform), 8.0));
+        assert!(close(MemSlice::new(uniform.repeat(3)).entropy(), 8.0));
+        assert_eq!(MemSlice::new(vec![1u8, 1, 2]).histogram().count(1), 2);
+    }
+
+    #[test]
+    fn windows_cover_the_range()
+    {
+        let mut data = vec![0u8; 0x100];
+        data.extend(0..=255u8);
+        let mem = BufferMemory::new(0x1000, data);
+
+        let w = entropy_windows(&mem, 0x1000, 0x200, 0x100, 0x100).unwrap();
+        assert_eq!(w.len(), 2);
+        assert_eq!((w[0].addr, w[0].size), (0x1000, 0x100));
+        assert_eq!(w[0].entropy, 0.0);
+        assert!(close(w[1].entropy, 8.0));
+
+        // Overlapping windows, the last one ends with the range
+        let w = entropy_windows(&mem, 0x1000, 0x180, 0x100, 0x80).unwrap();
+        assert_eq!(w.iter().map(|w| (w.addr, w.size)).collect::<Vec<_>>(), [(0x1000, 0x100), (0x1080, 0x100)]);
+
+        let w = entropy_windows(&mem, 0x1000, 0x150, 0x100, 0x100).unwrap();
+        assert_eq!(w.last().unwrap().size, 0x50);
+
+        assert!(entropy_windows(&mem, 0x1000, 0x100, 0, 1).is_err());
+        assert!(entropy_windows(&mem, 0x1000, 0x100, 1, 0).is_err());
+        assert!(entropy_windows(&mem, 0x1100, 0x200, 0x100, 0x100).is_err());
+        assert!(entropy_windows(&mem, 0x1000, 0, 0x100, 0x100).unwrap().is_empty());
+    }
+}
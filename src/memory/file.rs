@@ -0,0 +1,60 @@
+use crate::err::*;
+use super::MemorySource;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Memory source over a seekable reader, addresses are offsets in the stream
+// Only the requested bytes are read, the file is never loaded whole
+pub struct FileMemory<R: Read + Seek = File>
+{
+    reader: RefCell<R>,
+    len: usize,
+}
+
+impl FileMemory<File>
+{
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileMemory<File>, PEErr>
+    {
+        FileMemory::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> FileMemory<R>
+{
+    pub fn new(mut reader: R) -> Result<FileMemory<R>, PEErr>
+    {
+        let len = reader.seek(SeekFrom::End(0))? as usize;
+        Ok(FileMemory { reader: RefCell::new(reader), len })
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+}
+
+impl<R: Read + Seek> MemorySource for FileMemory<R>
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        match addr.checked_add(buf.len())
+        {
+            Some(end) if end <= self.len => (),
+            _ => return Err(PEErr::failure(format!("Read of {:#x} bytes at offset {:#x} is past the end of the file ({:#x})",
+                                                   buf.len(), addr, self.len))),
+        }
+
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(addr as u64))?;
+        reader.read_exact(buf)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use crate::err::*;
+use super::MemorySource;
+use std::fmt;
+use std::io;
+
+// What is printed in front of each line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressColumn
+{
+    Hidden,
+    Offset,             // Offset from the start of the data
+    Absolute(usize),    // Address of the first byte of the data
+}
+
+// Configurable hex dump formatter
+//
+// 00001000: 4c 8b d1 b8 18 00 00 00 0f 05 c3 00 00 00 00 00  L.......
+#[derive(Clone, Copy, Debug)]
+pub struct HexDump
+{
+    width: usize,           // Bytes per line
+    group: usize,           // Bytes per group, groups are separated by a space
+    address: AddressColumn,
+    ascii: bool,
+}
+
+impl Default for HexDump
+{
+    fn default() -> Self
+    {
+        HexDump::new()
+    }
+}
+
+impl HexDump
+{
+    pub fn new() -> HexDump
+    {
+        HexDump { width: 16, group: 1, address: AddressColumn::Offset, ascii: true }
+    }
+
+    pub fn width(mut self, width: usize) -> HexDump
+    {
+        self.width = width.max(1);
+        self
+    }
+
+    pub fn group(mut self, group: usize) -> HexDump
+    {
+        self.group = group.max(1);
+        self
+    }
+
+    pub fn address(mut self, address: AddressColumn) -> HexDump
+    {
+        self.address = address;
+        self
+    }
+
+    pub fn ascii(mut self, ascii: bool) -> HexDump
+    {
+        self.ascii = ascii;
+        self
+    }
+
+    pub fn format<W: fmt::Write, D: AsRef<[u8]> + ?Sized>(&self, out: &mut W, data: &D) -> fmt::Result
+    {
+        let data = data.as_ref();
+
+        // Pad every address to the size of the last one, with a minimum of 8 digits
+        let last = match self.address
+        {
+            AddressColumn::Absolute(base) => base.saturating_add(data.len()),
+            _ => data.len(),
+        };
+        let addr_width = format!("{:x}", last).len().max(8);
+
+        for (idx, line) in data.chunks(self.width).enumerate()
+        {
+            let offset = idx * self.width;
+
+            match self.address
+            {
+                AddressColumn::Hidden => (),
+                AddressColumn::Offset => write!(out, "{:0w$x}: ", offset, w = addr_width)?,
+                AddressColumn::Absolute(base) => write!(out, "{:0w$x}: ", base.wrapping_add(offset), w = addr_width)?,
+            }
+
+            // Partial lines are only padded when the ascii column needs to stay aligned
+            let columns = if self.ascii { self.width } else { line.len() };
+
+            for i in 0..columns
+            {
+                if i > 0 && i % self.group == 0
+                {
+                    out.write_char(' ')?;
+                }
+
+                match line.get(i)
+                {
+                    Some(b) => write!(out, "{:02x}", b)?,
+                    None => out.write_str("  ")?,
+                }
+            }
+
+            if self.ascii
+            {
+                out.write_str("  ")?;
+                for &b in line
+                {
+                    out.write_char(if (33..=126).contains(&b) { b as char } else { '.' })?;
+                }
+            }
+
+            out.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_io<W: io::Write, D: AsRef<[u8]> + ?Sized>(&self, out: &mut W, data: &D) -> io::Result<()>
+    {
+        out.write_all(self.dump(data).as_bytes())
+    }
+
+    pub fn dump<D: AsRef<[u8]> + ?Sized>(&self, data: &D) -> String
+    {
+        let mut s = String::new();
+        // Writing to a String cannot fail
+        let _ = self.format(&mut s, data);
+        s
+    }
+
+    // Dump size bytes read from a memory source, addresses are absolute unless hidden
+    pub fn dump_memory<M: MemorySource + ?Sized>(&self, src: &M, addr: usize, size: usize) -> Result<String, PEErr>
+    {
+        let data = src.read_vec(addr, size)?;
+
+        let dump = match self.address
+        {
+            AddressColumn::Hidden => *self,
+            _ => self.address(AddressColumn::Absolute(addr)),
+        };
+
+        Ok(dump.dump(&data))
+    }
+}
+
+// Parse a hex dump back into bytes (xxd -r style)
+// Accepts the output of HexDump with any width / grouping, with or without address and ascii columns
+pub fn parse_hex_dump(text: &str) -> Result<Vec<u8>, PEErr>
+{
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for (nb, line) in text.lines().enumerate()
+    {
+        let invalid = || PEErr::failure(format!("Invalid hex dump line {}: {}", nb + 1, line));
+
+        // Address column, if any
+        let mut rest = line.trim_start();
+        if let Some((addr, hex)) = rest.split_once(':')
+        {
+            if !addr.is_empty() && addr.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                rest = hex.strip_prefix(' ').unwrap_or(hex);
+            }
+        }
+
+        // The hex column ends at the first double space (start of the ascii column)
+        let hex = match rest.find("  ")
+        {
+            Some(end) => &rest[..end],
+            None => rest,
+        };
+
+        for token in hex.split(' ').filter(|t| !t.is_empty())
+        {
+            if token.len() % 2 != 0 || !token.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(invalid());
+            }
+
+            for i in (0..token.len()).step_by(2)
+            {
+                bytes.push(u8::from_str_radix(&token[i..i + 2], 16).map_err(|_| invalid())?);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    fn sample() -> Vec<u8>
+    {
+        // Every byte value, plus a partial last line
+        (0u8..=255).chain(b":  z".iter().copied()).collect()
+    }
+
+    #[test]
+    fn format_matches_the_documented_layout()
+    {
+        let dump = HexDump::new().dump(&[0x4c, 0x8b, 0xd1, 0xb8, 0x41, 0x42]);
+
+        assert_eq!(dump, "00000000: 4c 8b d1 b8 41 42                                L...AB\n");
+    }
+
+    #[test]
+    fn parse_round_trips_every_layout()
+    {
+        let data = sample();
+        let addresses = [AddressColumn::Hidden, AddressColumn::Offset, AddressColumn::Absolute(0x7ff6_1234_0000)];
+
+        for width in [1, 7, 16, 32]
+        {
+            for group in [1, 2, 4, 16]
+            {
+                for address in addresses
+                {
+                    for ascii in [false, true]
+                    {
+                        let dump = HexDump::new().width(width).group(group).address(address).ascii(ascii).dump(&data);
+
+                        assert_eq!(parse_hex_dump(&dump).unwrap(), data,
+                                   "width {} group {} address {:?} ascii {}", width, group, address, ascii);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dump_memory_uses_absolute_addresses()
+    {
+        let mem = BufferMemory::new(0x1000, sample());
+        let dump = HexDump::new().dump_memory(&mem, 0x1010, 0x20).unwrap();
+
+        assert!(dump.starts_with("00001010: 10 11"));
+        assert_eq!(parse_hex_dump(&dump).unwrap(), &sample()[0x10..0x30]);
+        assert!(HexDump::new().dump_memory(&mem, 0x1100, 0x10).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lines()
+    {
+        assert!(parse_hex_dump("00000000: 4c 8").is_err());
+        assert!(parse_hex_dump("00000000: 4c zz").is_err());
+        assert_eq!(parse_hex_dump("").unwrap(), Vec::<u8>::new());
+    }
+}
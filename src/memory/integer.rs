@@ -0,0 +1,333 @@
+use crate::err::*;
+use super::MemorySource;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Mul, Sub};
+
+// Integer whose width is only known at runtime
+// Values of different widths compare by value, arithmetic wraps at the widest operand
+#[derive(Clone, Copy, Debug)]
+pub enum Integer
+{
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    USize(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntWidth
+{
+    U8,
+    U16,
+    U32,
+    U64,
+    USize,  // Host pointer width
+}
+
+impl IntWidth
+{
+    pub fn size(self) -> usize
+    {
+        match self
+        {
+            IntWidth::U8 => 1,
+            IntWidth::U16 => 2,
+            IntWidth::U32 => 4,
+            IntWidth::U64 => 8,
+            IntWidth::USize => std::mem::size_of::<usize>(),
+        }
+    }
+
+    // Width of a pointer of the given size, for 32-bit or 64-bit targets
+    pub fn pointer(size: usize) -> Result<IntWidth, PEErr>
+    {
+        match size
+        {
+            4 => Ok(IntWidth::U32),
+            8 => Ok(IntWidth::U64),
+            _ => Err(PEErr::failure(format!("Unsupported pointer size: {}", size))),
+        }
+    }
+
+    fn mask(self) -> u64
+    {
+        match self.size()
+        {
+            8 => u64::MAX,
+            s => (1u64 << (s * 8)) - 1,
+        }
+    }
+}
+
+impl Integer
+{
+    // Truncates value to the given width
+    pub fn from_u64(value: u64, width: IntWidth) -> Integer
+    {
+        match width
+        {
+            IntWidth::U8 => Integer::U8(value as u8),
+            IntWidth::U16 => Integer::U16(value as u16),
+            IntWidth::U32 => Integer::U32(value as u32),
+            IntWidth::U64 => Integer::U64(value),
+            IntWidth::USize => Integer::USize(value as usize),
+        }
+    }
+
+    // Decode a little endian value, bytes must hold exactly width.size() bytes
+    pub fn from_le_bytes(bytes: &[u8], width: IntWidth) -> Result<Integer, PEErr>
+    {
+        if bytes.len() != width.size()
+        {
+            return Err(PEErr::failure(format!("Expected {} bytes for a {:?}, got {}", width.size(), width, bytes.len())));
+        }
+
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(Integer::from_u64(u64::from_le_bytes(buf), width))
+    }
+
+    pub fn to_le_bytes(self) -> Vec<u8>
+    {
+        self.to_u64().to_le_bytes()[..self.size()].to_vec()
+    }
+
+    pub fn width(&self) -> IntWidth
+    {
+        match self
+        {
+            Integer::U8(_) => IntWidth::U8,
+            Integer::U16(_) => IntWidth::U16,
+            Integer::U32(_) => IntWidth::U32,
+            Integer::U64(_) => IntWidth::U64,
+            Integer::USize(_) => IntWidth::USize,
+        }
+    }
+
+    pub fn size(&self) -> usize
+    {
+        self.width().size()
+    }
+
+    pub fn to_u64(self) -> u64
+    {
+        match self
+        {
+            Integer::U8(v) => v as u64,
+            Integer::U16(v) => v as u64,
+            Integer::U32(v) => v as u64,
+            Integer::U64(v) => v,
+            Integer::USize(v) => v as u64,
+        }
+    }
+
+    // Fails if the value does not fit in a host pointer
+    pub fn to_usize(self) -> Result<usize, PEErr>
+    {
+        usize::try_from(self.to_u64()).map_err(|_| PEErr::failure(format!("{:#x} does not fit in a usize", self)))
+    }
+
+    // Hex string padded to the width of the value: 0x0018 for a U16
+    pub fn to_hex(&self) -> String
+    {
+        format!("{:#0w$x}", self.to_u64(), w = self.size() * 2 + 2)
+    }
+
+    // Apply op on both values, the result has the width of the widest operand
+    fn combine(self, other: Integer, op: fn(u64, u64) -> u64) -> Integer
+    {
+        let width = self.width().max(other.width());
+        Integer::from_u64(op(self.to_u64(), other.to_u64()) & width.mask(), width)
+    }
+}
+
+// Read an integer of the given width (little endian)
+// With IntWidth::pointer(size), the same call reads 32-bit or 64-bit pointers
+pub fn read_integer<M: MemorySource + ?Sized>(src: &M, addr: usize, width: IntWidth) -> Result<Integer, PEErr>
+{
+    let mut buf = [0u8; 8];
+    src.read_bytes(addr, &mut buf[..width.size()])?;
+
+    Integer::from_le_bytes(&buf[..width.size()], width)
+}
+
+macro_rules! integer_from
+{
+    ($($t: ty => $v: ident),*) =>
+    {
+        $(
+            impl From<$t> for Integer
+            {
+                fn from(v: $t) -> Integer
+                {
+                    Integer::$v(v)
+                }
+            }
+        )*
+    };
+}
+
+integer_from!(u8 => U8, u16 => U16, u32 => U32, u64 => U64, usize => USize);
+
+impl From<Integer> for u64
+{
+    fn from(v: Integer) -> u64
+    {
+        v.to_u64()
+    }
+}
+
+macro_rules! integer_op
+{
+    ($($tr: ident, $f: ident, $op: expr);*) =>
+    {
+        $(
+            impl $tr for Integer
+            {
+                type Output = Integer;
+
+                fn $f(self, other: Integer) -> Integer
+                {
+                    self.combine(other, $op)
+                }
+            }
+        )*
+    };
+}
+
+integer_op!(Add, add, u64::wrapping_add;
+            Sub, sub, u64::wrapping_sub;
+            Mul, mul, u64::wrapping_mul;
+            BitAnd, bitand, |a, b| a & b;
+            BitOr, bitor, |a, b| a | b;
+            BitXor, bitxor, |a, b| a ^ b);
+
+impl PartialEq for Integer
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.to_u64() == other.to_u64()
+    }
+}
+
+impl Eq for Integer {}
+
+impl PartialOrd for Integer
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Integer
+{
+    fn cmp(&self, other: &Self) -> Ordering
+    {
+        self.to_u64().cmp(&other.to_u64())
+    }
+}
+
+// Consistent with the cross width equality
+impl Hash for Integer
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        self.to_u64().hash(state)
+    }
+}
+
+impl fmt::Display for Integer
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        fmt::Display::fmt(&self.to_u64(), f)
+    }
+}
+
+impl fmt::LowerHex for Integer
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        fmt::LowerHex::fmt(&self.to_u64(), f)
+    }
+}
+
+impl fmt::UpperHex for Integer
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        fmt::UpperHex::fmt(&self.to_u64(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+    use std::collections::HashSet;
+
+    #[test]
+    fn reads_every_width()
+    {
+        let mem = BufferMemory::new(0x1000, (1u8..=8).collect::<Vec<u8>>());
+
+        assert_eq!(read_integer(&mem, 0x1000, IntWidth::U8).unwrap(), Integer::U8(1));
+        assert_eq!(read_integer(&mem, 0x1000, IntWidth::U16).unwrap().to_u64(), 0x0201);
+        assert_eq!(read_integer(&mem, 0x1004, IntWidth::U32).unwrap().to_u64(), 0x08070605);
+        assert_eq!(read_integer(&mem, 0x1000, IntWidth::U64).unwrap().to_u64(), 0x0807060504030201);
+        assert!(read_integer(&mem, 0x1001, IntWidth::U64).is_err());
+
+        let ptr = read_integer(&mem, 0x1000, IntWidth::pointer(4).unwrap()).unwrap();
+        assert_eq!(ptr.width(), IntWidth::U32);
+        assert!(IntWidth::pointer(2).is_err());
+    }
+
+    #[test]
+    fn byte_conversions()
+    {
+        let v = Integer::from_le_bytes(&[0x34, 0x12], IntWidth::U16).unwrap();
+        assert_eq!(v, Integer::U16(0x1234));
+        assert_eq!(v.to_le_bytes(), [0x34, 0x12]);
+        assert!(Integer::from_le_bytes(&[0x34, 0x12], IntWidth::U32).is_err());
+
+        assert_eq!(Integer::from_u64(0x1_0000_00ff, IntWidth::U8), Integer::U8(0xff));
+        assert_eq!(Integer::U64(u64::MAX).to_usize().is_ok(), std::mem::size_of::<usize>() == 8);
+    }
+
+    #[test]
+    fn values_compare_across_widths()
+    {
+        assert_eq!(Integer::U8(0x18), Integer::U64(0x18));
+        assert!(Integer::U16(0x100) > Integer::U8(0xff));
+
+        let set: HashSet<Integer> = [Integer::U8(1), Integer::U32(1), Integer::U16(2)].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn arithmetic_wraps_at_the_widest_operand()
+    {
+        assert_eq!(Integer::U8(0xff) + Integer::U8(1), Integer::U8(0));
+        assert_eq!((Integer::U8(0xff) + Integer::U16(1)).width(), IntWidth::U16);
+        assert_eq!(Integer::U8(0xff) + Integer::U16(1), Integer::U16(0x100));
+        assert_eq!(Integer::U16(0) - Integer::U8(1), Integer::U16(0xffff));
+        assert_eq!(Integer::U32(0x10000) * Integer::U32(0x10000), Integer::U32(0));
+        assert_eq!(Integer::U8(0xf0) | Integer::U8(0x0f), Integer::U8(0xff));
+        assert_eq!(Integer::U8(0xf0) & Integer::U16(0x1ff), Integer::U16(0xf0));
+        assert_eq!(Integer::U8(0xff) ^ Integer::U8(0x0f), Integer::U8(0xf0));
+    }
+
+    #[test]
+    fn formatting_follows_the_width()
+    {
+        assert_eq!(Integer::U16(0x18).to_hex(), "0x0018");
+        assert_eq!(Integer::U64(0x18).to_hex(), "0x0000000000000018");
+        assert_eq!(format!("{} {:x} {:#X}", Integer::U8(42), Integer::U32(0xab), Integer::U32(0xab)), "42 ab 0xAB");
+    }
+}
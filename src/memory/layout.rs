@@ -0,0 +1,274 @@
+use crate::err::*;
+use super::{MemorySource, Bitness};
+
+// Declare an NT / PE structure as a list of named fields, each with its offset
+// in the 32-bit and in the 64-bit layout of the structure:
+//
+//  nt_struct!
+//  {
+//      // LIST_ENTRY
+//      pub struct ListEntry : ListEntryOffsets
+//      {
+//          flink: usize = 0x0, 0x0;
+//          blink: usize = 0x4, 0x8;
+//      }
+//  }
+//
+// Fields are any NtField: u8, u16, u32, u64, usize (a pointer of the target width),
+// byte arrays, NT strings or another nt_struct!.
+// Generates:
+//  - the structure, holding the value of every field
+//  - the offsets structure, holding the offset of every field
+//  - OFFSETS32 / OFFSETS64 and FIELDS (name, offset 32, offset 64) to audit the layout
+//  - NtField and NtStruct, so it can be nested or pointed to by a RemotePtr
+//  - read (for a given Bitness), read32 / read64, reading the structure from a memory source in a single read
+#[macro_export]
+macro_rules! nt_struct
+{
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $offsets:ident
+        {
+            $( $(#[$fmeta:meta])* $field:ident : $ty:ty = $off32:expr, $off64:expr; )*
+        }
+    ) =>
+    {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Default, PartialEq, Eq)]
+        $vis struct $name
+        {
+            $( $(#[$fmeta])* pub $field: $ty, )*
+        }
+
+        // Offset of every field of the structure
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        $vis struct $offsets
+        {
+            $( pub $field: usize, )*
+        }
+
+        impl $name
+        {
+            pub const OFFSETS32: $offsets = $offsets { $( $field: $off32, )* };
+            pub const OFFSETS64: $offsets = $offsets { $( $field: $off64, )* };
+
+            // (name, 32-bit offset, 64-bit offset) of every field, in declaration order
+            pub const FIELDS: &'static [(&'static str, usize, usize)] = &[ $( (stringify!($field), $off32, $off64), )* ];
+
+            pub fn offsets(bitness: $crate::memory::Bitness) -> $offsets
+            {
+                match bitness
+                {
+                    $crate::memory::Bitness::Bit32 => Self::OFFSETS32,
+                    $crate::memory::Bitness::Bit64 => Self::OFFSETS64,
+                }
+            }
+
+            pub fn read<M: $crate::memory::MemorySource + ?Sized>(src: &M, addr: usize, bitness: $crate::memory::Bitness)
+                -> Result<$name, $crate::err::PEErr>
+            {
+                <$name as $crate::memory::NtField>::read_field(src, addr, bitness)
+            }
+
+            pub fn read32<M: $crate::memory::MemorySource + ?Sized>(src: &M, addr: usize) -> Result<$name, $crate::err::PEErr>
+            {
+                Self::read(src, addr, $crate::memory::Bitness::Bit32)
+            }
+
+            pub fn read64<M: $crate::memory::MemorySource + ?Sized>(src: &M, addr: usize) -> Result<$name, $crate::err::PEErr>
+            {
+                Self::read(src, addr, $crate::memory::Bitness::Bit64)
+            }
+        }
+
+        impl $crate::memory::NtStruct for $name
+        {
+            type Offsets = $offsets;
+
+            fn offsets(bitness: $crate::memory::Bitness) -> $offsets
+            {
+                <$name>::offsets(bitness)
+            }
+        }
+
+        impl $crate::memory::NtField for $name
+        {
+            // Up to the end of the last declared field
+            fn size(bitness: $crate::memory::Bitness) -> usize
+            {
+                let _o = Self::offsets(bitness);
+                0 $( .max(_o.$field + <$ty as $crate::memory::NtField>::size(bitness)) )*
+            }
+
+            fn read_field<M: $crate::memory::MemorySource + ?Sized>(src: &M, addr: usize, bitness: $crate::memory::Bitness)
+                -> Result<$name, $crate::err::PEErr>
+            {
+                // Fetch the whole structure at once, then decode the fields from the copy
+                let _o = Self::offsets(bitness);
+                let _buf = $crate::memory::BufferMemory::new(addr, $crate::memory::MemorySource::read_vec(src, addr, Self::size(bitness))?);
+
+                Ok($name
+                {
+                    $( $field: <$ty as $crate::memory::NtField>::read_field(&_buf, addr + _o.$field, bitness)?, )*
+                })
+            }
+        }
+    };
+}
+
+// A value that can be a field of an nt_struct!
+// bitness is the one of the target, not the one of the host
+pub trait NtField: Sized
+{
+    // Number of bytes read
+    fn size(bitness: Bitness) -> usize;
+
+    fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<Self, PEErr>;
+}
+
+// Implemented by every nt_struct!, gives access to the offsets of its fields
+pub trait NtStruct: NtField
+{
+    type Offsets;
+
+    fn offsets(bitness: Bitness) -> Self::Offsets;
+}
+
+macro_rules! nt_field_int
+{
+    ($($t:ty),*) =>
+    {
+        $(
+            impl NtField for $t
+            {
+                fn size(_bitness: Bitness) -> usize
+                {
+                    std::mem::size_of::<$t>()
+                }
+
+                fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, _bitness: Bitness) -> Result<$t, PEErr>
+                {
+                    let mut buf = [0u8; std::mem::size_of::<$t>()];
+                    src.read_bytes(addr, &mut buf)?;
+                    Ok(<$t>::from_le_bytes(buf))
+                }
+            }
+        )*
+    };
+}
+
+nt_field_int!(u8, u16, u32, u64);
+
+// usize fields are pointers, sized after the target
+impl NtField for usize
+{
+    fn size(bitness: Bitness) -> usize
+    {
+        bitness.ptr_size()
+    }
+
+    fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<usize, PEErr>
+    {
+        src.read_ptr(addr, bitness)
+    }
+}
+
+// Arrays of fields, e.g. [u8; 8] names or [ImageDataDirectory; 16]
+impl<T: NtField + Default, const N: usize> NtField for [T; N]
+{
+    fn size(bitness: Bitness) -> usize
+    {
+        T::size(bitness) * N
+    }
+
+    fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<[T; N], PEErr>
+    {
+        let mut items: [T; N] = std::array::from_fn(|_| T::default());
+
+        for (i, item) in items.iter_mut().enumerate()
+        {
+            *item = T::read_field(src, addr + i * T::size(bitness), bitness)?;
+        }
+
+        Ok(items)
+    }
+}
+
+nt_struct!
+{
+    // LIST_ENTRY
+    pub struct ListEntry : ListEntryOffsets
+    {
+        flink: usize = 0x0, 0x0;
+        blink: usize = 0x4, 0x8;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    nt_struct!
+    {
+        // Pointers, a nested structure and an array, with differing layouts
+        struct Sample : SampleOffsets
+        {
+            tag:    u16         = 0x00, 0x00;
+            ptr:    usize       = 0x04, 0x08;
+            links:  ListEntry   = 0x08, 0x10;
+            bytes:  [u8; 3]     = 0x10, 0x20;
+            wide:   u64         = 0x14, 0x28;
+        }
+    }
+
+    fn memory() -> BufferMemory
+    {
+        BufferMemory::new(0x1000, (0u8..0x40).collect())
+    }
+
+    #[test]
+    fn offsets_and_sizes_follow_the_bitness()
+    {
+        assert_eq!(Sample::offsets(Bitness::Bit32).links, 0x08);
+        assert_eq!(Sample::offsets(Bitness::Bit64).links, 0x10);
+        assert_eq!(Sample::FIELDS[3], ("bytes", 0x10, 0x20));
+        assert_eq!(Sample::FIELDS.len(), 5);
+
+        assert_eq!(<Sample as NtField>::size(Bitness::Bit32), 0x1c);
+        assert_eq!(<Sample as NtField>::size(Bitness::Bit64), 0x30);
+        assert_eq!(<ListEntry as NtField>::size(Bitness::Bit32), 8);
+        assert_eq!(<[u16; 5] as NtField>::size(Bitness::Bit64), 10);
+    }
+
+    #[test]
+    fn reads_both_layouts()
+    {
+        let mem = memory();
+
+        let s = Sample::read32(&mem, 0x1000).unwrap();
+        assert_eq!(s.tag, 0x0100);
+        assert_eq!(s.ptr, 0x07060504);
+        assert_eq!(s.links, ListEntry { flink: 0x0b0a0908, blink: 0x0f0e0d0c });
+        assert_eq!(s.bytes, [0x10, 0x11, 0x12]);
+        assert_eq!(s.wide, 0x1b1a191817161514);
+
+        let s = Sample::read64(&mem, 0x1000).unwrap();
+        assert_eq!(s.ptr as u64, 0x0f0e0d0c0b0a0908);
+        assert_eq!(s.links.blink as u64, 0x1f1e1d1c1b1a1918);
+        assert_eq!(s.bytes, [0x20, 0x21, 0x22]);
+        assert_eq!(s, Sample::read(&mem, 0x1000, Bitness::Bit64).unwrap());
+    }
+
+    #[test]
+    fn truncated_structures_fail()
+    {
+        let mem = memory();
+
+        assert!(Sample::read64(&mem, 0x1010).is_ok());
+        assert!(Sample::read64(&mem, 0x1011).is_err());
+        assert!(Sample::read32(&mem, 0x1024).is_ok());
+        assert!(Sample::read32(&mem, 0x1025).is_err());
+    }
+}
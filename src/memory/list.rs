@@ -0,0 +1,303 @@
+use crate::err::*;
+use super::{MemorySource, Bitness, ListEntry};
+use std::collections::HashSet;
+
+// Entries walked before a list is considered corrupted
+const MAX_LIST_LEN: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListDirection
+{
+    Forward,    // Following Flink
+    Backward,   // Following Blink
+}
+
+// Walk of a doubly linked LIST_ENTRY, starting from its head
+// Yields the address of the record containing each entry (CONTAINING_RECORD), the head itself is not yielded
+// Stops with an error on a null link, a Blink / Flink not pointing back, a cycle not going through the head,
+// or a list longer than max_len
+pub struct ListEntryIter<'a, M: MemorySource + ?Sized>
+{
+    src: &'a M,
+    head: usize,
+    offset: usize,          // Offset of the LIST_ENTRY inside the containing record
+    bitness: Bitness,
+    direction: ListDirection,
+    max_len: usize,
+    current: usize,         // Address of the last LIST_ENTRY visited
+    links: Option<ListEntry>,
+    seen: HashSet<usize>,
+    done: bool,
+}
+
+impl<'a, M: MemorySource + ?Sized> ListEntryIter<'a, M>
+{
+    pub fn new(src: &'a M, head: usize, offset: usize) -> ListEntryIter<'a, M>
+    {
+        ListEntryIter { src,
+                        head,
+                        offset,
+                        bitness: Bitness::host(),
+                        direction: ListDirection::Forward,
+                        max_len: MAX_LIST_LEN,
+                        current: head,
+                        links: None,
+                        seen: HashSet::new(),
+                        done: false }
+    }
+
+    pub fn bitness(mut self, bitness: Bitness) -> Self
+    {
+        self.bitness = bitness;
+        self
+    }
+
+    pub fn direction(mut self, direction: ListDirection) -> Self
+    {
+        self.direction = direction;
+        self
+    }
+
+    pub fn backward(self) -> Self
+    {
+        self.direction(ListDirection::Backward)
+    }
+
+    pub fn max_len(mut self, max_len: usize) -> Self
+    {
+        self.max_len = max_len;
+        self
+    }
+
+    pub fn head(&self) -> usize
+    {
+        self.head
+    }
+
+    // Number of entries yielded so far
+    pub fn count_walked(&self) -> usize
+    {
+        self.seen.len()
+    }
+
+    fn step(&mut self) -> Result<Option<usize>, PEErr>
+    {
+        let links = match self.links.take()
+        {
+            Some(links) => links,
+            None => ListEntry::read(self.src, self.head, self.bitness)?,
+        };
+
+        let (next, link) = match self.direction
+        {
+            ListDirection::Forward => (links.flink, "Flink"),
+            ListDirection::Backward => (links.blink, "Blink"),
+        };
+
+        if next == self.head
+        {
+            return Ok(None);
+        }
+
+        if next == 0
+        {
+            return Err(PEErr::failure(format!("Null {} in the LIST_ENTRY at {:#x}", link, self.current)));
+        }
+
+        if self.seen.len() >= self.max_len
+        {
+            return Err(PEErr::failure(format!("List at {:#x} is longer than {} entries", self.head, self.max_len)));
+        }
+
+        if !self.seen.insert(next)
+        {
+            return Err(PEErr::failure(format!("List at {:#x} loops on {:#x} without going back to its head", self.head, next)));
+        }
+
+        // The entry we move to must point back to the one we come from
+        let next_links = ListEntry::read(self.src, next, self.bitness)?;
+        let (back, back_link) = match self.direction
+        {
+            ListDirection::Forward => (next_links.blink, "Blink"),
+            ListDirection::Backward => (next_links.flink, "Flink"),
+        };
+
+        if back != self.current
+        {
+            return Err(PEErr::failure(format!("Corrupted list: {} of {:#x} is {:#x} instead of {:#x}",
+                                              back_link, next, back, self.current)));
+        }
+
+        self.current = next;
+        self.links = Some(next_links);
+
+        next.checked_sub(self.offset)
+            .map(Some)
+            .ok_or_else(|| PEErr::failure(format!("Invalid list entry address {:#x}", next)))
+    }
+}
+
+impl<M: MemorySource + ?Sized> Iterator for ListEntryIter<'_, M>
+{
+    type Item = Result<usize, PEErr>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        if self.done
+        {
+            return None;
+        }
+
+        match self.step()
+        {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) =>
+            {
+                self.done = true;
+                None
+            },
+            Err(e) =>
+            {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::{BufferMemory, MemorySourceMut};
+
+    const BASE: usize = 0x10_0000;
+    const OFFSET: usize = 0x10;     // Offset of the LIST_ENTRY inside each record
+
+    // Head at BASE, records at BASE + 0x100 * n
+    fn list(records: usize, bitness: Bitness) -> BufferMemory
+    {
+        let mut mem = BufferMemory::new(BASE, vec![0u8; 0x100 * (records + 1)]);
+        let entries: Vec<usize> = std::iter::once(BASE).chain((1..=records).map(|n| BASE + 0x100 * n + OFFSET)).collect();
+
+        for (i, &entry) in entries.iter().enumerate()
+        {
+            let flink = entries[(i + 1) % entries.len()];
+            let blink = entries[(i + entries.len() - 1) % entries.len()];
+            link(&mut mem, entry, flink, blink, bitness);
+        }
+
+        mem
+    }
+
+    fn link(mem: &mut BufferMemory, entry: usize, flink: usize, blink: usize, bitness: Bitness)
+    {
+        let size = bitness.ptr_size();
+        mem.write_bytes(entry, &flink.to_le_bytes()[..size]).unwrap();
+        mem.write_bytes(entry + size, &blink.to_le_bytes()[..size]).unwrap();
+    }
+
+    fn records(n: usize) -> Vec<usize>
+    {
+        (1..=n).map(|n| BASE + 0x100 * n).collect()
+    }
+
+    #[test]
+    fn walks_both_directions()
+    {
+        for bitness in [Bitness::Bit32, Bitness::Bit64]
+        {
+            let mem = list(3, bitness);
+
+            let forward: Vec<usize> = ListEntryIter::new(&mem, BASE, OFFSET).bitness(bitness).map(|r| r.unwrap()).collect();
+            assert_eq!(forward, records(3));
+
+            let backward: Vec<usize> = ListEntryIter::new(&mem, BASE, OFFSET).bitness(bitness).backward()
+                                                                            .map(|r| r.unwrap()).collect();
+            assert_eq!(backward, records(3).into_iter().rev().collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn empty_list_yields_nothing()
+    {
+        let mem = list(0, Bitness::Bit64);
+
+        assert_eq!(ListEntryIter::new(&mem, BASE, OFFSET).bitness(Bitness::Bit64).count(), 0);
+    }
+
+    // Items yielded before the walk stops, and the error it stopped on
+    fn walk(mem: &BufferMemory) -> (Vec<usize>, String)
+    {
+        let mut ok = Vec::new();
+
+        for r in ListEntryIter::new(mem, BASE, OFFSET).bitness(Bitness::Bit64).max_len(8)
+        {
+            match r
+            {
+                Ok(record) => ok.push(record),
+                Err(e) => return (ok, e.message),
+            }
+        }
+
+        panic!("The walk of a corrupted list ended without an error");
+    }
+
+    #[test]
+    fn detects_a_bad_back_link()
+    {
+        let mut mem = list(3, Bitness::Bit64);
+        let second = BASE + 0x200 + OFFSET;
+        link(&mut mem, second, BASE + 0x300 + OFFSET, 0xdead_0000, Bitness::Bit64);
+
+        let (ok, err) = walk(&mem);
+        assert_eq!(ok, records(1));
+        assert!(err.starts_with("Corrupted list: Blink of"), "{}", err);
+    }
+
+    #[test]
+    fn detects_a_null_link()
+    {
+        let mut mem = list(2, Bitness::Bit64);
+        link(&mut mem, BASE + 0x200 + OFFSET, 0, BASE + 0x100 + OFFSET, Bitness::Bit64);
+
+        let (ok, err) = walk(&mem);
+        assert_eq!(ok, records(2));
+        assert!(err.starts_with("Null Flink"), "{}", err);
+    }
+
+    #[test]
+    fn detects_a_cycle_not_going_through_the_head()
+    {
+        // 1 -> 2 -> 3 -> 2, with consistent back-links up to the loop
+        let mut mem = list(3, Bitness::Bit64);
+        let entry = |n: usize| BASE + 0x100 * n + OFFSET;
+        link(&mut mem, entry(3), entry(2), entry(2), Bitness::Bit64);
+
+        let (ok, err) = walk(&mem);
+        assert_eq!(ok, records(3));
+        assert!(err.contains("without going back to its head"), "{}", err);
+    }
+
+    #[test]
+    fn detects_a_runaway_list()
+    {
+        let mem = list(9, Bitness::Bit64);
+
+        let (ok, err) = walk(&mem);
+        assert_eq!(ok, records(8));
+        assert!(err.contains("is longer than 8 entries"), "{}", err);
+    }
+
+    #[test]
+    fn stops_after_the_first_error()
+    {
+        let mut mem = list(2, Bitness::Bit64);
+        link(&mut mem, BASE + 0x100 + OFFSET, 0, BASE, Bitness::Bit64);
+
+        let mut it = ListEntryIter::new(&mem, BASE, OFFSET).bitness(Bitness::Bit64);
+        assert!(it.next().unwrap().is_ok());
+        assert!(it.next().unwrap().is_err());
+        assert!(it.next().is_none());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::err::*;
+use super::{MemorySource, MemCursor, Bitness, NtField, WideString, utf16_to_str};
+
+// Header shared by UNICODE_STRING and ANSI_STRING
+//  USHORT Length;          // In bytes, without terminator
+//  USHORT MaximumLength;   // In bytes, size of the buffer
+//  PVOID  Buffer;          // Pointer aligned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct NtStringHeader
+{
+    length: u16,
+    maximum_length: u16,
+    buffer: usize,
+}
+
+impl NtStringHeader
+{
+    // Buffer is a pointer of the target, aligned on its size
+    fn read<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<NtStringHeader, PEErr>
+    {
+        let ptr_size = bitness.ptr_size();
+        let mut c = MemCursor::new(src, addr, ptr_size * 2);
+
+        let length = c.read_u16()?;
+        let maximum_length = c.read_u16()?;
+        let buffer = c.seek(ptr_size)?.read_ptr(bitness)?;
+
+        Ok(NtStringHeader { length, maximum_length, buffer })
+    }
+
+    // Read exactly Length bytes from Buffer
+    fn read_buffer<M: MemorySource + ?Sized>(&self, src: &M, kind: &str) -> Result<Vec<u8>, PEErr>
+    {
+        if self.length > self.maximum_length
+        {
+            return Err(PEErr::failure(format!("{} Length ({:#x}) is greater than MaximumLength ({:#x})",
+                                              kind, self.length, self.maximum_length)));
+        }
+
+        if self.length == 0
+        {
+            return Ok(Vec::new());
+        }
+
+        if self.buffer == 0
+        {
+            return Err(PEErr::failure(format!("{} of length {:#x} has a null Buffer", kind, self.length)));
+        }
+
+        src.read_vec(self.buffer, self.length as usize)
+    }
+}
+
+// UNICODE_STRING, Buffer holds Length bytes of UTF-16LE
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnicodeString
+{
+    pub length: u16,
+    pub maximum_length: u16,
+    pub buffer: usize,
+}
+
+impl UnicodeString
+{
+    // Read the structure itself, located at addr
+    pub fn read<M: MemorySource + ?Sized>(src: &M, addr: usize) -> Result<UnicodeString, PEErr>
+    {
+        Self::read_field(src, addr, Bitness::host())
+    }
+
+    // Same as read, for a structure of a 32-bit or 64-bit target
+    pub fn read_with<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<UnicodeString, PEErr>
+    {
+        Self::read_field(src, addr, bitness)
+    }
+
+    // Follow Buffer and decode the Length bytes it points to
+    pub fn read_units<M: MemorySource + ?Sized>(&self, src: &M) -> Result<Vec<u16>, PEErr>
+    {
+        if !self.length.is_multiple_of(2)
+        {
+            return Err(PEErr::failure(format!("UNICODE_STRING has an odd Length ({:#x})", self.length)));
+        }
+
+        let h = NtStringHeader { length: self.length, maximum_length: self.maximum_length, buffer: self.buffer };
+        let bytes = h.read_buffer(src, "UNICODE_STRING")?;
+
+        Ok(bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect())
+    }
+
+    pub fn read_string<M: MemorySource + ?Sized>(&self, src: &M) -> Result<String, PEErr>
+    {
+        Ok(utf16_to_str(&self.read_units(src)?))
+    }
+
+    // Lossless, unpaired surrogates are kept
+    pub fn read_wide<M: MemorySource + ?Sized>(&self, src: &M) -> Result<WideString, PEErr>
+    {
+        Ok(WideString::from_units(self.read_units(src)?))
+    }
+}
+
+impl NtField for UnicodeString
+{
+    fn size(bitness: Bitness) -> usize
+    {
+        bitness.ptr_size() * 2
+    }
+
+    fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<UnicodeString, PEErr>
+    {
+        let h = NtStringHeader::read(src, addr, bitness)?;
+        Ok(UnicodeString { length: h.length, maximum_length: h.maximum_length, buffer: h.buffer })
+    }
+}
+
+// ANSI_STRING, Buffer holds Length bytes of 8-bit characters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnsiString
+{
+    pub length: u16,
+    pub maximum_length: u16,
+    pub buffer: usize,
+}
+
+impl AnsiString
+{
+    pub fn read<M: MemorySource + ?Sized>(src: &M, addr: usize) -> Result<AnsiString, PEErr>
+    {
+        Self::read_field(src, addr, Bitness::host())
+    }
+
+    // Same as read, for a structure of a 32-bit or 64-bit target
+    pub fn read_with<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<AnsiString, PEErr>
+    {
+        Self::read_field(src, addr, bitness)
+    }
+
+    pub fn read_bytes<M: MemorySource + ?Sized>(&self, src: &M) -> Result<Vec<u8>, PEErr>
+    {
+        let h = NtStringHeader { length: self.length, maximum_length: self.maximum_length, buffer: self.buffer };
+        h.read_buffer(src, "ANSI_STRING")
+    }
+
+    // Non UTF-8 bytes are replaced
+    pub fn read_string<M: MemorySource + ?Sized>(&self, src: &M) -> Result<String, PEErr>
+    {
+        Ok(String::from_utf8_lossy(&self.read_bytes(src)?).to_string())
+    }
+}
+
+impl NtField for AnsiString
+{
+    fn size(bitness: Bitness) -> usize
+    {
+        bitness.ptr_size() * 2
+    }
+
+    fn read_field<M: MemorySource + ?Sized>(src: &M, addr: usize, bitness: Bitness) -> Result<AnsiString, PEErr>
+    {
+        let h = NtStringHeader::read(src, addr, bitness)?;
+        Ok(AnsiString { length: h.length, maximum_length: h.maximum_length, buffer: h.buffer })
+    }
+}
+
+// Read the UNICODE_STRING located at addr and decode it
+pub fn read_unicode_string<M: MemorySource + ?Sized>(src: &M, addr: usize) -> Result<String, PEErr>
+{
+    UnicodeString::read(src, addr)?.read_string(src)
+}
+
+// Read the ANSI_STRING located at addr and decode it
+pub fn read_ansi_string<M: MemorySource + ?Sized>(src: &M, addr: usize) -> Result<String, PEErr>
+{
+    AnsiString::read(src, addr)?.read_string(src)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    const BASE: usize = 0x1000;
+
+    // Header at 0, buffer at 0x40
+    fn string(bitness: Bitness, length: u16, maximum_length: u16, buffer: usize, data: &[u8]) -> BufferMemory
+    {
+        let mut b = vec![0u8; 0x80];
+        b[0..2].copy_from_slice(&length.to_le_bytes());
+        b[2..4].copy_from_slice(&maximum_length.to_le_bytes());
+        match bitness
+        {
+            Bitness::Bit32 => b[4..8].copy_from_slice(&(buffer as u32).to_le_bytes()),
+            Bitness::Bit64 => b[8..16].copy_from_slice(&(buffer as u64).to_le_bytes()),
+        }
+        b[0x40..0x40 + data.len()].copy_from_slice(data);
+
+        BufferMemory::new(BASE, b)
+    }
+
+    fn utf16(s: &str) -> Vec<u8>
+    {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decodes_both_layouts()
+    {
+        for bitness in [Bitness::Bit32, Bitness::Bit64]
+        {
+            let data = utf16("ntdll.dll");
+            let mem = string(bitness, data.len() as u16, data.len() as u16 + 2, BASE + 0x40, &data);
+
+            let us = UnicodeString::read_with(&mem, BASE, bitness).unwrap();
+            assert_eq!(us, UnicodeString { length: 18, maximum_length: 20, buffer: BASE + 0x40 });
+            assert_eq!(us.read_string(&mem).unwrap(), "ntdll.dll");
+
+            let mem = string(bitness, 5, 6, BASE + 0x40, b"hello");
+            assert_eq!(AnsiString::read_with(&mem, BASE, bitness).unwrap().read_string(&mem).unwrap(), "hello");
+        }
+
+        let mem = string(Bitness::host(), 2, 2, BASE + 0x40, b"a\0");
+        assert_eq!(read_unicode_string(&mem, BASE).unwrap(), "a");
+        assert_eq!(read_ansi_string(&mem, BASE).unwrap(), "a\0");
+    }
+
+    #[test]
+    fn length_is_checked_against_maximum_length()
+    {
+        let mem = string(Bitness::Bit64, 8, 6, BASE + 0x40, &utf16("abcd"));
+        let err = UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_string(&mem).unwrap_err();
+        assert!(err.message.contains("greater than MaximumLength"));
+
+        let err = AnsiString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_bytes(&mem).unwrap_err();
+        assert!(err.message.starts_with("ANSI_STRING"));
+    }
+
+    #[test]
+    fn odd_lengths_are_rejected()
+    {
+        let mem = string(Bitness::Bit64, 3, 4, BASE + 0x40, &utf16("ab"));
+        let us = UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap();
+
+        assert!(us.read_units(&mem).unwrap_err().message.contains("odd Length"));
+
+        // Odd lengths are fine for 8-bit strings
+        assert_eq!(AnsiString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_bytes(&mem).unwrap(), b"a\0b");
+    }
+
+    #[test]
+    fn null_buffers()
+    {
+        // Empty string, the Buffer is never followed
+        let mem = string(Bitness::Bit64, 0, 0, 0, &[]);
+        assert_eq!(UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_string(&mem).unwrap(), "");
+
+        let mem = string(Bitness::Bit64, 4, 4, 0, &[]);
+        let err = UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_string(&mem).unwrap_err();
+        assert!(err.message.contains("null Buffer"));
+    }
+
+    #[test]
+    fn buffers_outside_of_the_source_fail()
+    {
+        let mem = string(Bitness::Bit64, 0x40, 0x40, BASE + 0x60, &[]);
+        assert!(UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap().read_string(&mem).is_err());
+        assert!(UnicodeString::read_with(&mem, BASE + 0x78, Bitness::Bit64).is_err());
+    }
+
+    #[test]
+    fn unpaired_surrogates()
+    {
+        let mem = string(Bitness::Bit64, 4, 4, BASE + 0x40, &[0x41, 0x00, 0x00, 0xd8]);
+        let us = UnicodeString::read_with(&mem, BASE, Bitness::Bit64).unwrap();
+
+        assert_eq!(us.read_string(&mem).unwrap(), "A\u{fffd}");
+        assert_eq!(us.read_wide(&mem).unwrap().units(), [0x41, 0xd800]);
+    }
+}
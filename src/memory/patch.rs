@@ -0,0 +1,255 @@
+use crate::err::*;
+use super::{MemorySource, MemorySourceMut, Integer};
+use std::fmt;
+
+// Write data at addr
+pub fn write_mem<M: MemorySourceMut + ?Sized>(dst: &mut M, addr: usize, data: &[u8]) -> Result<(), PEErr>
+{
+    dst.write_bytes(addr, data)
+}
+
+// Write an integer (little endian) using its own width
+pub fn write_integer<M: MemorySourceMut + ?Sized>(dst: &mut M, addr: usize, value: Integer) -> Result<(), PEErr>
+{
+    dst.write_bytes(addr, &value.to_le_bytes())
+}
+
+// A modification of memory, keeping the original bytes so that it can be reverted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch
+{
+    pub addr: usize,
+    pub original: Vec<u8>,
+    pub new: Vec<u8>,
+    applied: bool,
+}
+
+impl Patch
+{
+    // Record a patch of addr with new, the original bytes are read from src
+    pub fn new<M: MemorySource + ?Sized>(src: &M, addr: usize, new: &[u8]) -> Result<Patch, PEErr>
+    {
+        Ok(Patch { addr, original: src.read_vec(addr, new.len())?, new: new.to_vec(), applied: false })
+    }
+
+    pub fn is_applied(&self) -> bool
+    {
+        self.applied
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.new.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.new.is_empty()
+    }
+
+    // Write the new bytes, the memory must still hold the original ones
+    pub fn apply<M: MemorySourceMut + ?Sized>(&mut self, dst: &mut M) -> Result<(), PEErr>
+    {
+        if self.applied
+        {
+            return Err(PEErr::failure(format!("Patch at {:#x} is already applied", self.addr)));
+        }
+
+        self.expect(dst, &self.original, "original")?;
+        dst.write_bytes(self.addr, &self.new)?;
+        self.applied = true;
+
+        Ok(())
+    }
+
+    // Restore the original bytes, the memory must still hold the patched ones
+    pub fn revert<M: MemorySourceMut + ?Sized>(&mut self, dst: &mut M) -> Result<(), PEErr>
+    {
+        if !self.applied
+        {
+            return Err(PEErr::failure(format!("Patch at {:#x} is not applied", self.addr)));
+        }
+
+        self.expect(dst, &self.new, "patched")?;
+        dst.write_bytes(self.addr, &self.original)?;
+        self.applied = false;
+
+        Ok(())
+    }
+
+    fn expect<M: MemorySource + ?Sized>(&self, src: &M, expected: &[u8], what: &str) -> Result<(), PEErr>
+    {
+        if src.read_vec(self.addr, expected.len())? != expected
+        {
+            return Err(PEErr::failure(format!("Memory at {:#x} does not hold the {} bytes of the patch", self.addr, what)));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Patch
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let hex = |b: &[u8]| b.iter().map(|x| format!("{:02X}", x)).collect::<Vec<String>>().join(" ");
+
+        write!(f, "{:#x}: [ {} ] -> [ {} ]{}",
+                  self.addr,
+                  hex(&self.original),
+                  hex(&self.new),
+                  if self.applied { " (applied)" } else { "" })
+    }
+}
+
+// Ordered list of patches, reverted in reverse order so that overlapping patches unwind properly
+#[derive(Clone, Debug, Default)]
+pub struct PatchSet
+{
+    patches: Vec<Patch>,
+}
+
+impl PatchSet
+{
+    pub fn new() -> PatchSet
+    {
+        PatchSet { patches: Vec::new() }
+    }
+
+    // Record and apply a patch
+    pub fn patch<M: MemorySourceMut + ?Sized>(&mut self, dst: &mut M, addr: usize, new: &[u8]) -> Result<(), PEErr>
+    {
+        let mut p = Patch::new(&*dst, addr, new)?;
+        p.apply(dst)?;
+        self.patches.push(p);
+
+        Ok(())
+    }
+
+    pub fn push(&mut self, patch: Patch)
+    {
+        self.patches.push(patch);
+    }
+
+    pub fn patches(&self) -> &[Patch]
+    {
+        &self.patches
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Patch>
+    {
+        self.patches.iter()
+    }
+
+    pub fn apply_all<M: MemorySourceMut + ?Sized>(&mut self, dst: &mut M) -> Result<(), PEErr>
+    {
+        for p in self.patches.iter_mut().filter(|p| !p.is_applied())
+        {
+            p.apply(dst)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn revert_all<M: MemorySourceMut + ?Sized>(&mut self, dst: &mut M) -> Result<(), PEErr>
+    {
+        for p in self.patches.iter_mut().rev().filter(|p| p.is_applied())
+        {
+            p.revert(dst)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a PatchSet
+{
+    type Item = &'a Patch;
+    type IntoIter = std::slice::Iter<'a, Patch>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.patches.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    const BASE: usize = 0x1000;
+
+    fn buffer() -> BufferMemory
+    {
+        BufferMemory::new(BASE, (0u8..0x20).collect())
+    }
+
+    #[test]
+    fn write_helpers()
+    {
+        let mut mem = buffer();
+
+        write_mem(&mut mem, BASE + 2, &[0xaa, 0xbb]).unwrap();
+        write_integer(&mut mem, BASE + 4, Integer::from(0x11223344u32)).unwrap();
+
+        assert_eq!(mem.read_vec(BASE, 8).unwrap(), [0x00, 0x01, 0xaa, 0xbb, 0x44, 0x33, 0x22, 0x11]);
+        assert!(write_mem(&mut mem, BASE + 0x1f, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn apply_and_revert()
+    {
+        let mut mem = buffer();
+        let mut p = Patch::new(&mem, BASE + 4, &[0xc3, 0x90]).unwrap();
+
+        assert_eq!(p.original, [0x04, 0x05]);
+        assert!(p.revert(&mut mem).is_err());
+
+        p.apply(&mut mem).unwrap();
+        assert!(p.is_applied());
+        assert_eq!(mem.read_vec(BASE + 4, 2).unwrap(), [0xc3, 0x90]);
+        assert!(p.apply(&mut mem).is_err());
+
+        p.revert(&mut mem).unwrap();
+        assert!(!p.is_applied());
+        assert_eq!(mem.read_vec(BASE, 0x20).unwrap(), buffer().data());
+    }
+
+    #[test]
+    fn changed_memory_is_not_overwritten()
+    {
+        let mut mem = buffer();
+        let mut p = Patch::new(&mem, BASE, &[0xcc]).unwrap();
+
+        write_mem(&mut mem, BASE, &[0xff]).unwrap();
+        assert!(p.apply(&mut mem).is_err());
+        assert_eq!(mem.read_u8(BASE).unwrap(), 0xff);
+
+        write_mem(&mut mem, BASE, &[0x00]).unwrap();
+        p.apply(&mut mem).unwrap();
+        write_mem(&mut mem, BASE, &[0xff]).unwrap();
+        assert!(p.revert(&mut mem).is_err());
+        assert_eq!(mem.read_u8(BASE).unwrap(), 0xff);
+    }
+
+    #[test]
+    fn overlapping_patches_unwind_in_reverse_order()
+    {
+        let mut mem = buffer();
+        let mut set = PatchSet::new();
+
+        set.patch(&mut mem, BASE + 2, &[0xaa, 0xaa, 0xaa]).unwrap();
+        set.patch(&mut mem, BASE + 3, &[0xbb, 0xbb, 0xbb]).unwrap();
+        assert_eq!(mem.read_vec(BASE + 2, 4).unwrap(), [0xaa, 0xbb, 0xbb, 0xbb]);
+        assert_eq!(set.patches()[1].original, [0xaa, 0xaa, 0x05]);
+
+        set.revert_all(&mut mem).unwrap();
+        assert_eq!(mem.data(), buffer().data());
+        assert!(set.iter().all(|p| !p.is_applied()));
+
+        set.apply_all(&mut mem).unwrap();
+        assert_eq!(mem.read_vec(BASE + 2, 4).unwrap(), [0xaa, 0xbb, 0xbb, 0xbb]);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::err::*;
+use super::{MemorySource, MemSlice};
+use std::fmt;
+use std::str::FromStr;
+
+// Size of the blocks read from a memory source while scanning
+const SCAN_CHUNK: usize = 0x10000;
+
+// Byte pattern with wildcards, parsed from IDA-style strings:
+// "4C 8B D1 B8 ?? ?? ?? ??", "?" is a whole byte wildcard, "4?" / "?C" are nibble wildcards
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern
+{
+    bytes: Vec<u8>,     // Expected values, already masked
+    masks: Vec<u8>,     // Bits that must match
+    anchor: usize,      // Start of the longest run of fully specified bytes
+    anchor_len: usize,
+}
+
+impl Pattern
+{
+    pub fn parse(pattern: &str) -> Result<Pattern, PEErr>
+    {
+        let mut bytes = Vec::new();
+        let mut masks = Vec::new();
+
+        for token in pattern.split_whitespace()
+        {
+            let invalid = || PEErr::failure(format!("Invalid pattern token '{}' in '{}'", token, pattern));
+
+            let (value, mask) = match token.as_bytes()
+            {
+                b"?" | b"??" => (0, 0),
+                [hi, lo] =>
+                {
+                    let (hi, hi_mask) = nibble(*hi).ok_or_else(invalid)?;
+                    let (lo, lo_mask) = nibble(*lo).ok_or_else(invalid)?;
+                    ((hi << 4) | lo, (hi_mask << 4) | lo_mask)
+                },
+                _ => return Err(invalid()),
+            };
+
+            bytes.push(value);
+            masks.push(mask);
+        }
+
+        if bytes.is_empty()
+        {
+            return Err(PEErr::failure("Empty pattern"));
+        }
+
+        Ok(Pattern::with_masks(bytes, masks))
+    }
+
+    // Pattern without wildcards
+    pub fn from_bytes(bytes: &[u8]) -> Pattern
+    {
+        Pattern::with_masks(bytes.to_vec(), vec![0xff; bytes.len()])
+    }
+
+    fn with_masks(mut bytes: Vec<u8>, masks: Vec<u8>) -> Pattern
+    {
+        for (b, m) in bytes.iter_mut().zip(masks.iter())
+        {
+            *b &= m;
+        }
+
+        // The longest fully specified run is searched first, the rest is only checked on candidates
+        let (mut anchor, mut anchor_len) = (0, 0);
+        let mut start = 0;
+        for i in 0..=masks.len()
+        {
+            if i == masks.len() || masks[i] != 0xff
+            {
+                if i - start > anchor_len
+                {
+                    anchor = start;
+                    anchor_len = i - start;
+                }
+                start = i + 1;
+            }
+        }
+
+        Pattern { bytes, masks, anchor, anchor_len }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.bytes.is_empty()
+    }
+
+    // Does the pattern match the start of data
+    pub fn matches(&self, data: &[u8]) -> bool
+    {
+        data.len() >= self.len()
+            && data.iter().zip(self.bytes.iter().zip(self.masks.iter())).all(|(d, (b, m))| d & m == *b)
+    }
+
+    // Offsets of every match inside data
+    pub fn find_all(&self, data: &[u8]) -> Vec<usize>
+    {
+        let mut found = Vec::new();
+
+        if data.len() < self.len()
+        {
+            return found;
+        }
+
+        let last = data.len() - self.len();
+
+        if self.anchor_len == 0
+        {
+            found.extend((0..=last).filter(|&i| self.matches(&data[i..])));
+            return found;
+        }
+
+        let anchor = &self.bytes[self.anchor..self.anchor + self.anchor_len];
+        let first = anchor[0];
+
+        // Candidates are found on the first byte of the anchor
+        let mut pos = self.anchor;
+        let end = last + self.anchor;
+        while pos <= end
+        {
+            match data[pos..=end].iter().position(|&b| b == first)
+            {
+                Some(i) => pos += i,
+                None => break,
+            }
+
+            let start = pos - self.anchor;
+            if data[pos..pos + anchor.len()] == *anchor && self.matches(&data[start..])
+            {
+                found.push(start);
+            }
+
+            pos += 1;
+        }
+
+        found
+    }
+
+    pub fn find_in_slice(&self, slice: &MemSlice<u8>) -> Vec<usize>
+    {
+        self.find_all(slice)
+    }
+
+    // Addresses of every match in [addr..addr+size] of a memory source
+    // The range is read in chunks, overlapping so that matches across chunks are found
+    pub fn scan<M: MemorySource + ?Sized>(&self, src: &M, addr: usize, size: usize) -> Result<Vec<usize>, PEErr>
+    {
+        let mut found = Vec::new();
+        let overlap = self.len() - 1;
+        let mut offset = 0;
+
+        loop
+        {
+            let len = SCAN_CHUNK.max(self.len()).min(size - offset);
+            let data = src.read_vec(addr + offset, len)?;
+
+            found.extend(self.find_all(&data).into_iter().map(|i| addr + offset + i));
+
+            if offset + len >= size
+            {
+                break;
+            }
+
+            offset += len - overlap;
+        }
+
+        Ok(found)
+    }
+}
+
+impl FromStr for Pattern
+{
+    type Err = PEErr;
+
+    fn from_str(s: &str) -> Result<Pattern, PEErr>
+    {
+        Pattern::parse(s)
+    }
+}
+
+impl fmt::Display for Pattern
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        for (i, (b, m)) in self.bytes.iter().zip(self.masks.iter()).enumerate()
+        {
+            if i > 0
+            {
+                write!(f, " ")?;
+            }
+
+            let hi = if m & 0xf0 != 0 { format!("{:X}", b >> 4) } else { String::from("?") };
+            let lo = if m & 0x0f != 0 { format!("{:X}", b & 0xf) } else { String::from("?") };
+            write!(f, "{}{}", hi, lo)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Value and mask of one pattern nibble
+fn nibble(c: u8) -> Option<(u8, u8)>
+{
+    match c
+    {
+        b'?' => Some((0, 0)),
+        _ => (c as char).to_digit(16).map(|v| (v as u8, 0xf)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    const BASE: usize = 0x40_0000;
+
+    fn buffer_with(at: &[usize], bytes: &[u8]) -> BufferMemory
+    {
+        let mut data = vec![0u8; 2 * SCAN_CHUNK + 0x10];
+        for &off in at
+        {
+            data[off..off + bytes.len()].copy_from_slice(bytes);
+        }
+
+        BufferMemory::new(BASE, data)
+    }
+
+    #[test]
+    fn parse_and_display()
+    {
+        let p = Pattern::parse("4C 8B d1 B8 ?? ?? 0? ?0").unwrap();
+
+        assert_eq!(p.len(), 8);
+        assert_eq!(p.to_string(), "4C 8B D1 B8 ?? ?? 0? ?0");
+        assert!(Pattern::parse("4C 8").is_err());
+        assert!(Pattern::parse("4C ZZ").is_err());
+    }
+
+    #[test]
+    fn wildcards_match_any_nibble()
+    {
+        let p = Pattern::parse("B8 ?? 0? ?0").unwrap();
+
+        assert_eq!(p.find_all(&[0xb8, 0x55, 0x0f, 0xf0, 0xb8, 0x00, 0x10, 0x00]), vec![0]);
+        assert_eq!(p.find_all(&[0xb8, 0x55, 0x0f]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn scan_finds_matches_across_chunk_boundaries()
+    {
+        let bytes = [0x4c, 0x8b, 0xd1, 0xb8, 0x18, 0x00, 0x00, 0x00];
+        // The second chunk starts 7 bytes before the end of the first one, the third 7 bytes before the end of the second
+        let at = [0, SCAN_CHUNK - 7, SCAN_CHUNK + 0x100, 2 * SCAN_CHUNK - 10, 2 * SCAN_CHUNK + 0x10 - bytes.len()];
+        let mem = buffer_with(&at, &bytes);
+        let p = Pattern::parse("4C 8B D1 B8 ?? 00 00 00").unwrap();
+
+        let size = mem.len();
+        assert_eq!(p.scan(&mem, BASE, size).unwrap(), at.iter().map(|a| BASE + a).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn scan_reports_each_match_once()
+    {
+        // Matches inside the overlap of two chunks must not be reported twice
+        let mem = buffer_with(&[SCAN_CHUNK - 2], &[0x0f, 0x05]);
+        let p = Pattern::parse("0F 05").unwrap();
+
+        assert_eq!(p.scan(&mem, BASE, mem.len()).unwrap(), vec![BASE + SCAN_CHUNK - 2]);
+    }
+
+    #[test]
+    fn scan_fails_outside_of_the_source()
+    {
+        let mem = buffer_with(&[], &[]);
+        let p = Pattern::parse("0F 05").unwrap();
+
+        assert!(p.scan(&mem, BASE, mem.len() + 1).is_err());
+    }
+}
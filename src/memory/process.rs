@@ -0,0 +1,106 @@
+use crate::err::*;
+use super::{MemorySource, MemoryRegion, parse_proc_maps};
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+
+// Memory of another process, read through /proc/<pid>/mem
+// Reading requires ptrace access to the target (same user and ptrace_scope permitting, or a child)
+pub struct ProcessMemory
+{
+    pid: u32,
+    mem: File,
+}
+
+impl ProcessMemory
+{
+    pub fn open(pid: u32) -> Result<ProcessMemory, PEErr>
+    {
+        let mem = File::open(format!("/proc/{}/mem", pid))
+                      .map_err(|e| PEErr::failure(format!("Cannot open the memory of process {}: {}", pid, e)))?;
+
+        Ok(ProcessMemory { pid, mem })
+    }
+
+    pub fn pid(&self) -> u32
+    {
+        self.pid
+    }
+
+    // Current mappings of the process, from /proc/<pid>/maps
+    pub fn regions(&self) -> Result<Vec<MemoryRegion>, PEErr>
+    {
+        parse_proc_maps(&fs::read_to_string(format!("/proc/{}/maps", self.pid))?)
+    }
+}
+
+impl MemorySource for ProcessMemory
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        self.mem.read_exact_at(buf, addr as u64)
+                .map_err(|e| PEErr::failure(format!("Read of {:#x} bytes at {:#x} in process {} failed: {}",
+                                                    buf.len(), addr, self.pid, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::process::{Child, Command};
+    use std::time::Duration;
+
+    // Kill the child even when an assertion fails
+    struct Spawned(Child);
+
+    impl Drop for Spawned
+    {
+        fn drop(&mut self)
+        {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    #[test]
+    fn reads_the_mapped_image_of_a_child()
+    {
+        let child = Spawned(Command::new("sleep").arg("30").spawn().expect("Cannot spawn sleep"));
+        let pid = child.0.id();
+
+        let mem = ProcessMemory::open(pid).unwrap();
+        assert_eq!(mem.pid(), pid);
+
+        // Wait for the exec to map the executable, its first mapping starts with the ELF header
+        let own = fs::read_link("/proc/self/exe").unwrap();
+        let mut image = None;
+        for _ in 0..100
+        {
+            if let Some(exe) = fs::read_link(format!("/proc/{}/exe", pid)).ok().filter(|exe| *exe != own)
+            {
+                image = mem.regions().unwrap()
+                           .into_iter()
+                           .find(|r| r.offset == 0 && r.protection.read && r.path.as_deref() == exe.to_str())
+                           .map(|r| (exe, r));
+                if image.is_some()
+                {
+                    break;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        let (exe, image) = image.expect("No mapping of the executable");
+
+        assert_eq!(mem.read_vec(image.base, 4).unwrap(), b"\x7fELF");
+        assert_eq!(mem.read_vec(image.base, 4).unwrap(), fs::read(&exe).unwrap()[..4]);
+
+        // Unmapped memory is an error, not a crash
+        assert!(mem.read_u8(0).is_err());
+    }
+
+    #[test]
+    fn open_fails_for_a_missing_process()
+    {
+        assert!(ProcessMemory::open(u32::MAX).is_err());
+    }
+}
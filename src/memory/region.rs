@@ -0,0 +1,353 @@
+use crate::err::*;
+use super::{MemorySource, BufferMemory};
+use std::fmt;
+
+// Access rights of a memory region
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Protection
+{
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub shared: bool,
+}
+
+impl Protection
+{
+    // Parse the "rwxp" permission field of /proc/<pid>/maps
+    pub fn from_perms(perms: &str) -> Result<Protection, PEErr>
+    {
+        let p = perms.as_bytes();
+        if p.len() != 4
+        {
+            return Err(PEErr::failure(format!("Invalid permission field: {}", perms)));
+        }
+
+        Ok(Protection { read: p[0] == b'r', write: p[1] == b'w', execute: p[2] == b'x', shared: p[3] == b's' })
+    }
+}
+
+impl fmt::Display for Protection
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}{}{}{}",
+                  if self.read { 'r' } else { '-' },
+                  if self.write { 'w' } else { '-' },
+                  if self.execute { 'x' } else { '-' },
+                  if self.shared { 's' } else { 'p' })
+    }
+}
+
+// A contiguous range of mapped memory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegion
+{
+    pub base: usize,
+    pub size: usize,
+    pub protection: Protection,
+    pub offset: usize,          // Offset of the mapping in the backing file
+    pub path: Option<String>,   // Backing file, or pseudo path like [heap]
+}
+
+impl MemoryRegion
+{
+    pub fn end(&self) -> usize
+    {
+        self.base + self.size
+    }
+
+    pub fn contains(&self, addr: usize) -> bool
+    {
+        addr >= self.base && addr < self.end()
+    }
+}
+
+impl fmt::Display for MemoryRegion
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:#x}-{:#x} {} {:#x} {}",
+                  self.base,
+                  self.end(),
+                  self.protection,
+                  self.offset,
+                  self.path.as_deref().unwrap_or(""))
+    }
+}
+
+// Parse the content of a /proc/<pid>/maps file
+pub fn parse_proc_maps(content: &str) -> Result<Vec<MemoryRegion>, PEErr>
+{
+    content.lines()
+           .filter(|l| !l.trim().is_empty())
+           .map(parse_maps_line)
+           .collect()
+}
+
+// 7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 1234    /usr/lib/libc.so.6
+fn parse_maps_line(line: &str) -> Result<MemoryRegion, PEErr>
+{
+    let invalid = || PEErr::failure(format!("Invalid maps line: {}", line));
+
+    // range, perms, offset, dev and inode are space separated, the path is the rest of the line
+    let mut fields: Vec<&str> = Vec::with_capacity(5);
+    let mut rest = line;
+    for _ in 0..5
+    {
+        rest = rest.trim_start();
+        let end = rest.find(' ').unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let (start, end) = fields[0].split_once('-').ok_or_else(invalid)?;
+    let start = usize::from_str_radix(start, 16).map_err(|_| invalid())?;
+    let end = usize::from_str_radix(end, 16).map_err(|_| invalid())?;
+    let offset = usize::from_str_radix(fields[2], 16).map_err(|_| invalid())?;
+
+    if end < start
+    {
+        return Err(invalid());
+    }
+
+    let path = rest.trim();
+
+    Ok(MemoryRegion { base: start,
+                      size: end - start,
+                      protection: Protection::from_perms(fields[1])?,
+                      offset,
+                      path: if path.is_empty() { None } else { Some(String::from(path)) } })
+}
+
+// A file mapped in memory, possibly spread over several regions (one per segment / section)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedFile
+{
+    pub base: usize,
+    pub size: usize,
+    pub path: String,
+}
+
+// Sorted set of regions, used to validate addresses before reading them
+#[derive(Clone, Debug, Default)]
+pub struct RegionMap
+{
+    regions: Vec<MemoryRegion>,
+}
+
+impl RegionMap
+{
+    pub fn new(mut regions: Vec<MemoryRegion>) -> RegionMap
+    {
+        regions.sort_by_key(|r| r.base);
+        RegionMap { regions }
+    }
+
+    // Mappings of the current process
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Result<RegionMap, PEErr>
+    {
+        Ok(RegionMap::new(parse_proc_maps(&std::fs::read_to_string("/proc/self/maps")?)?))
+    }
+
+    // Mappings of another process
+    #[cfg(target_os = "linux")]
+    pub fn from_pid(pid: u32) -> Result<RegionMap, PEErr>
+    {
+        Ok(RegionMap::new(parse_proc_maps(&std::fs::read_to_string(format!("/proc/{}/maps", pid))?)?))
+    }
+
+    // Single read-write region covering a buffer
+    pub fn from_buffer<B: AsRef<[u8]>>(buffer: &BufferMemory<B>) -> RegionMap
+    {
+        RegionMap::new(vec![MemoryRegion { base: buffer.base_addr,
+                                           size: buffer.len(),
+                                           protection: Protection { read: true, write: true, execute: false, shared: false },
+                                           offset: 0,
+                                           path: None }])
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion]
+    {
+        &self.regions
+    }
+
+    fn index_of(&self, addr: usize) -> Option<usize>
+    {
+        // Last region starting at or before addr
+        let idx = self.regions.partition_point(|r| r.base <= addr).checked_sub(1)?;
+
+        if self.regions[idx].contains(addr) { Some(idx) } else { None }
+    }
+
+    pub fn region_containing(&self, addr: usize) -> Option<&MemoryRegion>
+    {
+        self.index_of(addr).map(|idx| &self.regions[idx])
+    }
+
+    // File mapping containing addr, spanning every adjacent region backed by the same file
+    pub fn image_containing(&self, addr: usize) -> Option<MappedFile>
+    {
+        let idx = self.index_of(addr)?;
+        let path = self.regions[idx].path.as_deref().filter(|p| p.starts_with('/'))?;
+        let same = |r: &MemoryRegion| r.path.as_deref() == Some(path);
+
+        let first = self.regions[..idx].iter().rposition(|r| !same(r)).map_or(0, |i| i + 1);
+        let last = self.regions[idx..].iter().position(|r| !same(r)).map_or(self.regions.len(), |i| idx + i) - 1;
+
+        Some(MappedFile { base: self.regions[first].base,
+                          size: self.regions[last].end() - self.regions[first].base,
+                          path: String::from(path) })
+    }
+
+    // Ensure [addr..addr+size] is covered by contiguous readable regions
+    pub fn check_read(&self, addr: usize, size: usize) -> Result<(), PEErr>
+    {
+        let invalid = || PEErr::failure(format!("Read of {:#x} bytes at {:#x} hits unmapped or unreadable memory", size, addr));
+
+        let end = addr.checked_add(size).ok_or_else(invalid)?;
+        let mut idx = self.index_of(addr).ok_or_else(invalid)?;
+        let mut covered = addr;
+
+        loop
+        {
+            let r = self.regions.get(idx).filter(|r| r.contains(covered) && r.protection.read).ok_or_else(invalid)?;
+            covered = r.end();
+
+            if covered >= end
+            {
+                return Ok(());
+            }
+
+            idx += 1;
+        }
+    }
+
+    pub fn is_readable(&self, addr: usize, size: usize) -> bool
+    {
+        self.check_read(addr, size).is_ok()
+    }
+
+    // Wrap a memory source so that every read is validated against this map first
+    pub fn guard<M: MemorySource>(&self, inner: M) -> CheckedMemory<'_, M>
+    {
+        CheckedMemory { map: self, inner }
+    }
+}
+
+// Memory source whose reads are validated against a RegionMap
+pub struct CheckedMemory<'a, M: MemorySource>
+{
+    map: &'a RegionMap,
+    inner: M,
+}
+
+impl<M: MemorySource> MemorySource for CheckedMemory<'_, M>
+{
+    fn read_bytes(&self, addr: usize, buf: &mut [u8]) -> Result<(), PEErr>
+    {
+        self.map.check_read(addr, buf.len())?;
+        self.inner.read_bytes(addr, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const MAPS: &str = "\
+55d0c0a00000-55d0c0a02000 r--p 00000000 fe:00 318229                     /usr/bin/sleep
+55d0c0a02000-55d0c0a07000 r-xp 00002000 fe:00 318229                     /usr/bin/sleep
+55d0c0a07000-55d0c0a09000 r--p 00007000 fe:00 318229                     /usr/bin/sleep
+55d0c1e4a000-55d0c1e6b000 rw-p 00000000 00:00 0                          [heap]
+7f1c2a000000-7f1c2a001000 ---p 00000000 00:00 0 
+7f1c2a001000-7f1c2a002000 rw-s 00000000 00:01 42                         /dev/shm/with space (deleted)
+ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]
+";
+
+    fn map() -> RegionMap
+    {
+        RegionMap::new(parse_proc_maps(MAPS).unwrap())
+    }
+
+    #[test]
+    fn parses_proc_maps()
+    {
+        let regions = parse_proc_maps(MAPS).unwrap();
+        assert_eq!(regions.len(), 7);
+
+        assert_eq!(regions[1], MemoryRegion { base: 0x55d0c0a02000,
+                                              size: 0x5000,
+                                              protection: Protection { read: true, write: false, execute: true, shared: false },
+                                              offset: 0x2000,
+                                              path: Some(String::from("/usr/bin/sleep")) });
+        assert_eq!(regions[4].path, None);
+        assert_eq!(regions[5].path.as_deref(), Some("/dev/shm/with space (deleted)"));
+        assert!(regions[5].protection.shared);
+        assert_eq!(regions[6].end(), 0xffffffffff601000);
+
+        assert_eq!(regions[1].to_string(), "0x55d0c0a02000-0x55d0c0a07000 r-xp 0x2000 /usr/bin/sleep");
+    }
+
+    #[test]
+    fn rejects_malformed_lines()
+    {
+        assert!(parse_proc_maps("55d0c0a00000 r--p 00000000 fe:00 1 /a").is_err());
+        assert!(parse_proc_maps("2000-1000 r--p 00000000 fe:00 1 /a").is_err());
+        assert!(parse_proc_maps("1000-2000 r-p 00000000 fe:00 1 /a").is_err());
+        assert!(parse_proc_maps("1000-2000 r--p zz fe:00 1 /a").is_err());
+        assert!(parse_proc_maps("\n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_may_span_adjacent_regions()
+    {
+        let map = map();
+
+        // The three segments of the executable are contiguous and readable
+        assert!(map.check_read(0x55d0c0a01ff0, 0x20).is_ok());
+        assert!(map.check_read(0x55d0c0a00000, 0x9000).is_ok());
+        assert!(map.check_read(0x55d0c0a00000, 0x9001).is_err());
+
+        // Hole between the executable and the heap
+        assert!(map.check_read(0x55d0c0a09000, 1).is_err());
+
+        // Adjacent but not readable
+        assert!(map.check_read(0x7f1c2a000ff0, 0x20).is_err());
+        assert!(map.check_read(0x7f1c2a001000, 0x1000).is_ok());
+        assert!(map.check_read(0xffffffffff600000, 1).is_err());
+
+        assert!(map.check_read(usize::MAX, 2).is_err());
+        assert!(!map.is_readable(0, 1));
+    }
+
+    #[test]
+    fn finds_the_image_containing_an_address()
+    {
+        let map = map();
+
+        let image = map.image_containing(0x55d0c0a07123).unwrap();
+        assert_eq!(image, MappedFile { base: 0x55d0c0a00000, size: 0x9000, path: String::from("/usr/bin/sleep") });
+        assert_eq!(map.image_containing(0x55d0c0a00000), Some(image));
+
+        // Pseudo paths and anonymous memory are not files
+        assert_eq!(map.image_containing(0x55d0c1e4a000), None);
+        assert_eq!(map.image_containing(0x7f1c2a000000), None);
+        assert_eq!(map.image_containing(0x1000), None);
+        assert_eq!(map.region_containing(0x55d0c1e6afff).unwrap().path.as_deref(), Some("[heap]"));
+    }
+
+    #[test]
+    fn guarded_reads_are_checked()
+    {
+        let mem = BufferMemory::new(0x1000, vec![0xaa; 0x100]);
+        let mut map = RegionMap::from_buffer(&mem);
+        assert!(map.guard(&mem).read_u8(0x10ff).is_ok());
+
+        map = RegionMap::new(vec![MemoryRegion { size: 0x80, ..map.regions()[0].clone() }]);
+        assert!(map.guard(&mem).read_u8(0x107f).is_ok());
+        assert!(map.guard(&mem).read_u8(0x1080).is_err());
+    }
+}
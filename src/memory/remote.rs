@@ -0,0 +1,244 @@
+use crate::err::*;
+use super::{MemorySource, Bitness, NtField, NtStruct};
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Add, Sub};
+
+// Typed address of a T living inside a memory source
+// Reading and following pointers uses the bitness of the target, not the one of the host
+pub struct RemotePtr<'a, T, M: MemorySource + ?Sized>
+{
+    src: &'a M,
+    addr: usize,
+    bitness: Bitness,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, M: MemorySource + ?Sized> RemotePtr<'a, T, M>
+{
+    pub fn new(src: &'a M, addr: usize) -> RemotePtr<'a, T, M>
+    {
+        RemotePtr::with_bitness(src, addr, Bitness::host())
+    }
+
+    pub fn with_bitness(src: &'a M, addr: usize, bitness: Bitness) -> RemotePtr<'a, T, M>
+    {
+        RemotePtr { src, addr, bitness, _type: PhantomData }
+    }
+
+    pub fn addr(&self) -> usize
+    {
+        self.addr
+    }
+
+    pub fn bitness(&self) -> Bitness
+    {
+        self.bitness
+    }
+
+    pub fn source(&self) -> &'a M
+    {
+        self.src
+    }
+
+    pub fn is_null(&self) -> bool
+    {
+        self.addr == 0
+    }
+
+    // None for a null pointer
+    pub fn non_null(self) -> Option<RemotePtr<'a, T, M>>
+    {
+        if self.is_null() { None } else { Some(self) }
+    }
+
+    // Same address, seen as another type
+    pub fn cast<U>(&self) -> RemotePtr<'a, U, M>
+    {
+        RemotePtr::with_bitness(self.src, self.addr, self.bitness)
+    }
+
+    // Pointer to the U located offset bytes after this one
+    pub fn field_at<U>(&self, offset: usize) -> RemotePtr<'a, U, M>
+    {
+        RemotePtr::with_bitness(self.src, self.addr.wrapping_add(offset), self.bitness)
+    }
+
+    pub fn byte_add(&self, count: usize) -> RemotePtr<'a, T, M>
+    {
+        self.field_at(count)
+    }
+
+    pub fn byte_sub(&self, count: usize) -> RemotePtr<'a, T, M>
+    {
+        RemotePtr::with_bitness(self.src, self.addr.wrapping_sub(count), self.bitness)
+    }
+}
+
+impl<'a, T: NtField, M: MemorySource + ?Sized> RemotePtr<'a, T, M>
+{
+    pub fn read(&self) -> Result<T, PEErr>
+    {
+        if self.is_null()
+        {
+            return Err(PEErr::failure(format!("Read of a null {}", std::any::type_name::<T>())));
+        }
+
+        T::read_field(self.src, self.addr, self.bitness)
+    }
+
+    // Size of T on the target
+    pub fn size(&self) -> usize
+    {
+        T::size(self.bitness)
+    }
+}
+
+impl<'a, T: NtStruct, M: MemorySource + ?Sized> RemotePtr<'a, T, M>
+{
+    // Pointer to a field of the structure, selected from the offsets of the target layout:
+    //  peb.field::<usize>(|o| o.ldr)
+    pub fn field<U>(&self, offset: impl FnOnce(&T::Offsets) -> usize) -> RemotePtr<'a, U, M>
+    {
+        self.field_at(offset(&T::offsets(self.bitness)))
+    }
+}
+
+impl<'a, M: MemorySource + ?Sized> RemotePtr<'a, usize, M>
+{
+    // Follow the pointer stored at this address
+    pub fn deref_ptr<U>(&self) -> Result<RemotePtr<'a, U, M>, PEErr>
+    {
+        Ok(RemotePtr::with_bitness(self.src, self.read()?, self.bitness))
+    }
+}
+
+// Pointer arithmetic, in number of T
+impl<'a, T: NtField, M: MemorySource + ?Sized> Add<usize> for RemotePtr<'a, T, M>
+{
+    type Output = RemotePtr<'a, T, M>;
+
+    fn add(self, count: usize) -> Self::Output
+    {
+        self.byte_add(count.wrapping_mul(self.size()))
+    }
+}
+
+impl<'a, T: NtField, M: MemorySource + ?Sized> Sub<usize> for RemotePtr<'a, T, M>
+{
+    type Output = RemotePtr<'a, T, M>;
+
+    fn sub(self, count: usize) -> Self::Output
+    {
+        self.byte_sub(count.wrapping_mul(self.size()))
+    }
+}
+
+impl<T, M: MemorySource + ?Sized> Clone for RemotePtr<'_, T, M>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+
+impl<T, M: MemorySource + ?Sized> Copy for RemotePtr<'_, T, M> {}
+
+// Pointers are equal when they point to the same address
+impl<T, M: MemorySource + ?Sized> PartialEq for RemotePtr<'_, T, M>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.addr == other.addr
+    }
+}
+
+impl<T, M: MemorySource + ?Sized> Eq for RemotePtr<'_, T, M> {}
+
+impl<T, M: MemorySource + ?Sized> fmt::Debug for RemotePtr<'_, T, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "RemotePtr<{}>({:#x}, {})", std::any::type_name::<T>(), self.addr, self.bitness)
+    }
+}
+
+impl<T, M: MemorySource + ?Sized> fmt::Display for RemotePtr<'_, T, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:#x}", self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::{BufferMemory, ListEntry};
+
+    // LIST_ENTRY at 0x1000 pointing to 0x1010 and 0x1020, in the given width
+    fn memory(bitness: Bitness) -> BufferMemory
+    {
+        let mut b = vec![0u8; 0x40];
+        let ps = bitness.ptr_size();
+        b[..ps].copy_from_slice(&0x1010u64.to_le_bytes()[..ps]);
+        b[ps..2 * ps].copy_from_slice(&0x1020u64.to_le_bytes()[..ps]);
+        b[0x10..0x14].copy_from_slice(&0xdeadbeefu32.to_le_bytes());
+        b[0x14..0x18].copy_from_slice(&0xcafef00du32.to_le_bytes());
+
+        BufferMemory::new(0x1000, b)
+    }
+
+    #[test]
+    fn follows_pointers_of_the_target_width()
+    {
+        for bitness in [Bitness::Bit32, Bitness::Bit64]
+        {
+            let mem = memory(bitness);
+            let head = RemotePtr::<ListEntry, _>::with_bitness(&mem, 0x1000, bitness);
+
+            assert_eq!(head.size(), 2 * bitness.ptr_size());
+            assert_eq!(head.read().unwrap(), ListEntry { flink: 0x1010, blink: 0x1020 });
+
+            let blink = head.field::<usize>(|o| o.blink);
+            assert_eq!(blink.addr(), 0x1000 + bitness.ptr_size());
+
+            let flink = head.field::<usize>(|o| o.flink).deref_ptr::<u32>().unwrap();
+            assert_eq!(flink.addr(), 0x1010);
+            assert_eq!(flink.read().unwrap(), 0xdeadbeef);
+            assert_eq!((flink + 1).read().unwrap(), 0xcafef00d);
+            assert_eq!(((flink + 1) - 1), flink);
+        }
+    }
+
+    #[test]
+    fn null_and_out_of_range_reads_fail()
+    {
+        let mem = memory(Bitness::Bit64);
+
+        let null = RemotePtr::<u32, _>::new(&mem, 0);
+        assert!(null.is_null());
+        assert!(null.non_null().is_none());
+        assert!(null.read().unwrap_err().message.contains("null"));
+
+        // The pointer at 0x1030 is null
+        let ptr = RemotePtr::<usize, _>::with_bitness(&mem, 0x1030, Bitness::Bit64);
+        assert!(ptr.deref_ptr::<u32>().unwrap().non_null().is_none());
+        assert!(RemotePtr::<u64, _>::new(&mem, 0x103c).read().is_err());
+    }
+
+    #[test]
+    fn arithmetic_and_formatting()
+    {
+        let mem = memory(Bitness::Bit64);
+        let p = RemotePtr::<u16, _>::new(&mem, 0x1000);
+
+        assert_eq!((p + 3).addr(), 0x1006);
+        assert_eq!(p.byte_add(3).addr(), 0x1003);
+        assert_eq!(p.byte_sub(0x1001).addr(), usize::MAX);
+        assert_eq!(p.cast::<u64>().field_at::<u8>(8).addr(), 0x1008);
+        assert_eq!(p.to_string(), "0x1000");
+        assert!(format!("{:?}", p).starts_with("RemotePtr<u16>(0x1000"));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::err::*;
+use super::MemorySource;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringEncoding
+{
+    Ascii,
+    Utf16Le,
+}
+
+// A run of printable text
+// offset is relative to the scanned data for slices, an address for memory ranges and an RVA for PE sections
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FoundString
+{
+    pub offset: usize,
+    pub encoding: StringEncoding,
+    pub value: String,
+    pub section: Option<String>,
+}
+
+fn is_printable(b: u8) -> bool
+{
+    (0x20..=0x7e).contains(&b) || b == b'\t'
+}
+
+// Runs of printable ASCII and UTF-16LE text of at least min_len characters, sorted by offset
+pub fn extract_strings(data: &[u8], min_len: usize) -> Vec<FoundString>
+{
+    let min_len = min_len.max(1);
+    let mut found = Vec::new();
+
+    // ASCII
+    let mut start = 0;
+    for i in 0..=data.len()
+    {
+        if i < data.len() && is_printable(data[i])
+        {
+            continue;
+        }
+
+        if i - start >= min_len
+        {
+            found.push(FoundString { offset: start,
+                                     encoding: StringEncoding::Ascii,
+                                     value: String::from_utf8_lossy(&data[start..i]).to_string(),
+                                     section: None });
+        }
+        start = i + 1;
+    }
+
+    // UTF-16LE, limited to the printable ASCII range, at both alignments
+    for align in 0..2
+    {
+        let units: Vec<(usize, u8)> = data.get(align..)
+                                          .unwrap_or(&[])
+                                          .chunks_exact(2)
+                                          .enumerate()
+                                          .map(|(i, c)| (align + i * 2, if c[1] == 0 && is_printable(c[0]) { c[0] } else { 0 }))
+                                          .collect();
+
+        let mut run: Vec<u8> = Vec::new();
+        let mut run_start = 0;
+        for (off, c) in units.iter().copied().chain(std::iter::once((data.len(), 0)))
+        {
+            if c != 0
+            {
+                if run.is_empty()
+                {
+                    run_start = off;
+                }
+                run.push(c);
+                continue;
+            }
+
+            if run.len() >= min_len
+            {
+                found.push(FoundString { offset: run_start,
+                                         encoding: StringEncoding::Utf16Le,
+                                         value: String::from_utf8_lossy(&run).to_string(),
+                                         section: None });
+            }
+            run.clear();
+        }
+    }
+
+    found.sort_by_key(|s| s.offset);
+    found
+}
+
+// Same as extract_strings, over [addr..addr+size] of a memory source, offsets are addresses
+pub fn extract_strings_from<M: MemorySource + ?Sized>(src: &M, addr: usize, size: usize, min_len: usize) -> Result<Vec<FoundString>, PEErr>
+{
+    let data = src.read_vec(addr, size)?;
+
+    Ok(extract_strings(&data, min_len).into_iter()
+                                      .map(|s| FoundString { offset: addr + s.offset, ..s })
+                                      .collect())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    fn values(found: &[FoundString]) -> Vec<(usize, StringEncoding, &str)>
+    {
+        found.iter().map(|s| (s.offset, s.encoding, s.value.as_str())).collect()
+    }
+
+    #[test]
+    fn finds_ascii_runs()
+    {
+        let data = b"\x00\x01hello\x00ab\x00world\tx\xffend";
+
+        assert_eq!(values(&extract_strings(data, 4)), [(2, StringEncoding::Ascii, "hello"),
+                                                       (11, StringEncoding::Ascii, "world\tx")]);
+        assert_eq!(extract_strings(data, 3).len(), 3);
+        assert!(extract_strings(data, 8).is_empty());
+        assert!(extract_strings(b"", 4).is_empty());
+    }
+
+    #[test]
+    fn finds_utf16_runs_at_both_alignments()
+    {
+        let mut data = vec![0xffu8];
+        data.extend("wide".encode_utf16().flat_map(|c| c.to_le_bytes()));
+        data.extend([0x00, 0x00, 0x00]);
+        data.extend("text!".encode_utf16().flat_map(|c| c.to_le_bytes()));
+
+        assert_eq!(values(&extract_strings(&data, 4)), [(1, StringEncoding::Utf16Le, "wide"),
+                                                        (12, StringEncoding::Utf16Le, "text!")]);
+
+        // Non ASCII code units end the run
+        let data: Vec<u8> = "ab\u{e9}cdef".encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+        assert_eq!(values(&extract_strings(&data, 3)), [(6, StringEncoding::Utf16Le, "cdef")]);
+    }
+
+    #[test]
+    fn memory_ranges_report_addresses()
+    {
+        let mem = BufferMemory::new(0x1000, b"....some text here....".to_vec());
+
+        let found = extract_strings_from(&mem, 0x1004, 0x0e, 4).unwrap();
+        assert_eq!(values(&found), [(0x1004, StringEncoding::Ascii, "some text here")]);
+        assert!(extract_strings_from(&mem, 0x1010, 0x100, 4).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::err::*;
+use super::utf16_to_str;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+// UTF-16 string as Windows stores it, units are kept as is (lone surrogates included)
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WideString
+{
+    units: Vec<u16>,
+}
+
+// Upper case of a single UTF-16 unit, an approximation of RtlUpcaseUnicodeChar:
+// only simple one to one mappings inside the BMP, surrogates and expanding mappings (e.g. 'ß') are kept.
+// Mappings come from the Unicode tables of the Rust standard library, not from the upcase table of Windows,
+// which predates some of them: e.g. Georgian U+10D0 is upcased to U+1C90 (Unicode 11), Windows keeps it.
+pub fn upcase_unit(unit: u16) -> u16
+{
+    if unit < 0x80
+    {
+        return (unit as u8).to_ascii_uppercase() as u16;
+    }
+
+    let c = match char::from_u32(unit as u32)
+    {
+        Some(c) => c,
+        None => return unit,
+    };
+
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next())
+    {
+        (Some(u), None) if (u as u32) <= 0xffff => u as u16,
+        _ => unit,
+    }
+}
+
+impl WideString
+{
+    pub fn new() -> WideString
+    {
+        WideString { units: Vec::new() }
+    }
+
+    pub fn from_units<U: Into<Vec<u16>>>(units: U) -> WideString
+    {
+        WideString { units: units.into() }
+    }
+
+    // Units up to the first null, or all of them
+    pub fn from_units_z(units: &[u16]) -> WideString
+    {
+        let len = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+        WideString::from_units(&units[..len])
+    }
+
+    pub fn units(&self) -> &[u16]
+    {
+        &self.units
+    }
+
+    pub fn into_units(self) -> Vec<u16>
+    {
+        self.units
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.units.is_empty()
+    }
+
+    // Fails on unpaired surrogates
+    pub fn to_string_checked(&self) -> Result<String, PEErr>
+    {
+        String::from_utf16(&self.units).map_err(|_| PEErr::failure(format!("Invalid UTF-16: {:x?}", self.units)))
+    }
+
+    // Unpaired surrogates are replaced by U+FFFD
+    pub fn to_string_lossy(&self) -> String
+    {
+        utf16_to_str(&self.units)
+    }
+
+    // Bytes as stored in memory
+    pub fn to_le_bytes(&self) -> Vec<u8>
+    {
+        self.units.iter().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    pub fn to_upper(&self) -> WideString
+    {
+        WideString { units: self.units.iter().map(|&u| upcase_unit(u)).collect() }
+    }
+
+    // Case-insensitive comparison, as done by RtlEqualUnicodeString(.., TRUE)
+    pub fn eq_ignore_case(&self, other: &WideString) -> bool
+    {
+        self.units.len() == other.units.len()
+        && self.units.iter().zip(&other.units).all(|(&a, &b)| upcase_unit(a) == upcase_unit(b))
+    }
+
+    // Hash consistent with eq_ignore_case
+    pub fn hash_ignore_case<H: Hasher>(&self, state: &mut H)
+    {
+        self.units.len().hash(state);
+        for &u in &self.units
+        {
+            upcase_unit(u).hash(state);
+        }
+    }
+}
+
+impl From<&str> for WideString
+{
+    fn from(s: &str) -> WideString
+    {
+        WideString { units: s.encode_utf16().collect() }
+    }
+}
+
+impl From<String> for WideString
+{
+    fn from(s: String) -> WideString
+    {
+        WideString::from(s.as_str())
+    }
+}
+
+impl From<Vec<u16>> for WideString
+{
+    fn from(units: Vec<u16>) -> WideString
+    {
+        WideString { units }
+    }
+}
+
+impl FromStr for WideString
+{
+    type Err = PEErr;
+
+    fn from_str(s: &str) -> Result<WideString, PEErr>
+    {
+        Ok(WideString::from(s))
+    }
+}
+
+impl PartialEq<str> for WideString
+{
+    fn eq(&self, other: &str) -> bool
+    {
+        self.units.iter().copied().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for WideString
+{
+    fn eq(&self, other: &&str) -> bool
+    {
+        *self == **other
+    }
+}
+
+impl fmt::Display for WideString
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", self.to_string_lossy())
+    }
+}
+
+impl fmt::Debug for WideString
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:?}", self.to_string_lossy())
+    }
+}
+
+// WideString compared and hashed case-insensitively, e.g. as a HashMap key for module names
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreCase(pub WideString);
+
+impl PartialEq for IgnoreCase
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.0.eq_ignore_case(&other.0)
+    }
+}
+
+impl Eq for IgnoreCase {}
+
+impl Hash for IgnoreCase
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        self.0.hash_ignore_case(state)
+    }
+}
+
+impl From<WideString> for IgnoreCase
+{
+    fn from(s: WideString) -> IgnoreCase
+    {
+        IgnoreCase(s)
+    }
+}
+
+impl From<&str> for IgnoreCase
+{
+    fn from(s: &str) -> IgnoreCase
+    {
+        IgnoreCase(WideString::from(s))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn upcases_ascii_and_latin1()
+    {
+        assert_eq!(upcase_unit('a' as u16), 'A' as u16);
+        assert_eq!(upcase_unit('z' as u16), 'Z' as u16);
+        assert_eq!(upcase_unit('A' as u16), 'A' as u16);
+        assert_eq!(upcase_unit('1' as u16), '1' as u16);
+
+        assert_eq!(upcase_unit(0xe9), 0xc9);        // é
+        assert_eq!(upcase_unit(0xfe), 0xde);        // þ
+        assert_eq!(upcase_unit(0xff), 0x178);       // ÿ, upper case outside of Latin-1
+        assert_eq!(upcase_unit(0xf7), 0xf7);        // ÷
+    }
+
+    #[test]
+    fn expanding_mappings_are_kept()
+    {
+        assert_eq!(upcase_unit(0xdf), 0xdf);        // ß, SS once upcased
+        assert_eq!(upcase_unit(0xfb00), 0xfb00);    // ﬀ ligature
+        assert_eq!(WideString::from("straße").to_upper(), "STRAßE");
+    }
+
+    #[test]
+    fn surrogates_are_kept()
+    {
+        // 𐐨 DESERET SMALL LETTER LONG I, its upper case is outside of the BMP as well
+        let units: Vec<u16> = "\u{10428}".encode_utf16().collect();
+        assert_eq!(units.iter().map(|&u| upcase_unit(u)).collect::<Vec<u16>>(), units);
+
+        assert_eq!(upcase_unit(0xd800), 0xd800);
+        assert_eq!(upcase_unit(0xdfff), 0xdfff);
+    }
+
+    #[test]
+    fn compares_ignoring_case()
+    {
+        assert!(WideString::from("KERNEL32.DLL").eq_ignore_case(&WideString::from("kernel32.dll")));
+        assert!(WideString::from("\u{e9}t\u{e9}").eq_ignore_case(&WideString::from("\u{c9}T\u{c9}")));
+        assert!(!WideString::from("ntdll.dll").eq_ignore_case(&WideString::from("ntdll.dl")));
+        assert!(!WideString::from("stra\u{df}e").eq_ignore_case(&WideString::from("STRASSE")));
+
+        let set: HashSet<IgnoreCase> = ["ntdll.dll", "NTDLL.DLL", "kernel32.dll"].into_iter().map(IgnoreCase::from).collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&IgnoreCase::from("NtDll.Dll")));
+    }
+
+    #[test]
+    fn lone_surrogates_are_preserved()
+    {
+        let w = WideString::from_units(vec![0x41, 0xd800, 0x42]);
+
+        assert_eq!(w.len(), 3);
+        assert_eq!(w.to_string_lossy(), "A\u{fffd}B");
+        assert!(w.to_string_checked().is_err());
+        assert_eq!(w.to_le_bytes(), [0x41, 0x00, 0x00, 0xd8, 0x42, 0x00]);
+        assert_ne!(w, WideString::from("A\u{fffd}B"));
+
+        assert_eq!(WideString::from_units_z(&[0x61, 0x62, 0, 0x63]), "ab");
+        assert_eq!(WideString::from_units_z(&[0x61]), "a");
+    }
+}
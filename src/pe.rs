@@ -1,11 +1,26 @@
 use crate::err::*;
+use crate::memory::{MemorySource, MemCursor, LiveMemory, BufferMemory, FileMemory, CachedMemory, NtField, Bitness, Pattern, FoundString,
+                    ByteHistogram, MemSlice, WideString, NullRead, extract_strings, read_cstr_limited};
 use std::fmt;
+use std::path::Path;
+
+mod headers;
+mod imports;
+mod section;
+pub use imports::{ImportThunk, ImportedFunction, ImportedDll, Imports};
+pub use section::Section;
+pub use headers::{ImageDosHeader, ImageDosHeaderOffsets, ImageFileHeader, ImageFileHeaderOffsets,
+                  ImageDataDirectory, ImageDataDirectoryOffsets, ImageOptionalHeader32, ImageOptionalHeader32Offsets,
+                  ImageOptionalHeader64, ImageOptionalHeader64Offsets, ImageSectionHeader, ImageSectionHeaderOffsets,
+                  ImageExportDirectory, ImageExportDirectoryOffsets, ImageImportDescriptor, ImageImportDescriptorOffsets,
+                  OptionalHeader, DataDirectory, Machine, Subsystem,
+                  FileCharacteristics, DllCharacteristics, SectionCharacteristics, IMAGE_DOS_SIGNATURE, IMAGE_NT_SIGNATURE,
+                  IMAGE_NT_OPTIONAL_HDR32_MAGIC, IMAGE_NT_OPTIONAL_HDR64_MAGIC, IMAGE_NUMBEROF_DIRECTORY_ENTRIES,
+                  IMAGE_ORDINAL_FLAG32, IMAGE_ORDINAL_FLAG64};
 
 /* TODO:
  * Name formatting:
  *  fn that states addr of when it is actually an offset
- * Features:
- *  provide FNs to get mem addr of a PE section, and FNs to get offset relative to base addr
  */
 
 // =================================================== PEName Enum
@@ -31,121 +46,788 @@ impl fmt::Display for PEName
 
 // =================================================== PEName Enum
 
+// Longest export / dll name accepted before the name is considered corrupted
+// MSVC truncates decorated names to 4096 characters
+const MAX_NAME_LEN: usize = 0x1000;
+
+// How the image is laid out in its memory source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout
+{
+    Mapped,     // Mapped by the loader, RVAs are offsets from the base address
+    File,       // Raw file on disk, RVAs are translated through the section table
+}
+
+// Entropy of a section, as it would be seen once mapped
+#[derive(Clone, Debug)]
+pub struct SectionEntropy
+{
+    pub name: String,
+    pub virtual_address: usize,
+    pub size: usize,
+    pub entropy: f64,
+    pub histogram: ByteHistogram,
+}
+
 #[derive(Debug)]
-pub struct PEImage
+pub struct PEImage<M: MemorySource = LiveMemory>
 {
     pub base_addr: usize,
+    mem: M,
+    layout: Layout,
+    read_base: usize,       // Address of the first byte of the image in the memory source
     name: PEName,
+    size_of_image: usize,
+    size_of_headers: usize,
+    sections: Vec<Section>,
+    section_alignment: usize,
+    file_alignment: usize,
     optional_header_offset: u32,
+    bitness: Bitness,       // PE32 or PE32+, from the optional header magic
     export_directory_offset: u32,
     export_directory_addr: usize,
     exp_dir_base: usize,
-    fnames: Vec<String>,
+    fnames: Vec<Option<String>>,   // None when the name could not be read
     fnames_ordinals: Vec<usize>,
+    export_issues: Vec<String>,     // Export entries that could not be parsed
 }
 
-impl PEImage
+impl PEImage<LiveMemory>
 {
-    pub fn new(addr: usize) -> PEImage
+    /// # Safety
+    /// `addr` must be the base of an image mapped in the current process
+    pub unsafe fn new(addr: usize) -> Result<PEImage, PEErr>
     {
         PEImage::from(addr, PEName::Empty)
     }
 
-    pub fn from(base_addr: usize, name: PEName) -> PEImage
+    /// # Safety
+    /// `base_addr` must be the base of an image mapped in the current process
+    pub unsafe fn from(base_addr: usize, name: PEName) -> Result<PEImage, PEErr>
+    {
+        PEImage::with_source(LiveMemory::new(), base_addr, name)
+    }
+}
+
+impl PEImage<CachedMemory<FileMemory>>
+{
+    // Parse a PE file from disk, reading only what is needed
+    // The file is read through a page cache, parsing does many small reads
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<PEImage<CachedMemory<FileMemory>>, PEErr>
+    {
+        PEImage::with_layout(CachedMemory::new(FileMemory::open(path)?), 0, PEName::Empty, Layout::File)
+    }
+}
+
+impl<'a> PEImage<BufferMemory<&'a [u8]>>
+{
+    // Parse the raw content of a PE file
+    pub fn from_file_bytes(bytes: &'a [u8]) -> Result<PEImage<BufferMemory<&'a [u8]>>, PEErr>
     {
-        let mut pe = PEImage { base_addr,
+        PEImage::with_layout(BufferMemory::new(0, bytes), 0, PEName::Empty, Layout::File)
+    }
+}
+
+impl<M: MemorySource> PEImage<M>
+{
+    // Parse an image mapped at base_addr inside the given memory source
+    pub fn with_source(mem: M, base_addr: usize, name: PEName) -> Result<PEImage<M>, PEErr>
+    {
+        PEImage::with_layout(mem, base_addr, name, Layout::Mapped)
+    }
+
+    // Parse an image starting at addr inside the given memory source
+    // For the File layout, base_addr is then set to the preferred ImageBase
+    // Fails when the headers are not the ones of a valid PE image
+    pub fn with_layout(mem: M, addr: usize, name: PEName, layout: Layout) -> Result<PEImage<M>, PEErr>
+    {
+        let mut pe = PEImage { base_addr: addr,
+                               mem,
+                               layout,
+                               read_base: addr,
                                name,
+                               size_of_image: 0,
+                               size_of_headers: 0,
+                               sections: Vec::new(),
+                               section_alignment: 0,
+                               file_alignment: 0,
                                optional_header_offset: 0,
+                               bitness: Bitness::host(),
                                export_directory_offset: 0,
                                export_directory_addr: 0,
                                exp_dir_base: 0,
                                fnames: Vec::new(),
                                fnames_ordinals: Vec::new(),
+                               export_issues: Vec::new(),
                              };
-        unsafe
+
+        pe.init()?;
+
+        Ok(pe)
+    }
+
+    pub fn source(&self) -> &M
+    {
+        &self.mem
+    }
+
+    // Mutable access to the source, e.g. to patch a buffer
+    // Exports are parsed at construction, patching them is not reflected on this PEImage
+    pub fn source_mut(&mut self) -> &mut M
+    {
+        &mut self.mem
+    }
+
+    pub fn layout(&self) -> Layout
+    {
+        self.layout
+    }
+
+    // 32-bit for PE32 images, 64-bit for PE32+ images
+    pub fn bitness(&self) -> Bitness
+    {
+        self.bitness
+    }
+
+    // Bounds-checked window over the whole image
+    // For the File layout, the window covers the headers and the raw data of every section
+    pub fn image(&self) -> MemCursor<'_, M>
+    {
+        let len = match self.layout
+        {
+            Layout::Mapped => self.size_of_image,
+            Layout::File => self.sections.iter()
+                                         .map(|s| self.raw_start(s) + self.raw_extent(s))
+                                         .fold(self.size_of_headers, usize::max),
+        };
+
+        MemCursor::new(&self.mem, self.read_base, len)
+    }
+
+    // Cursor positioned at the given RVA
+    // For the File layout the window is limited to the raw data of the section containing the RVA
+    fn at(&self, rva: usize) -> Result<MemCursor<'_, M>, PEErr>
+    {
+        if self.layout == Layout::Mapped || rva < self.size_of_headers
+        {
+            let len = if self.layout == Layout::Mapped { self.size_of_image } else { self.size_of_headers };
+            let mut c = self.image().sub(0, len)?;
+            c.seek(rva)?;
+            return Ok(c);
+        }
+
+        let s = self.section_for_rva(rva)
+                    .ok_or_else(|| PEErr::failure(format!("RVA {:#x} is not inside any section", rva)))?;
+
+        let delta = rva - s.virtual_address;
+        if delta >= self.raw_extent(s)
         {
-            pe.init();
+            return Err(PEErr::failure(format!("RVA {:#x} is not backed by file data", rva)));
         }
 
-        pe
+        let mut c = self.image().sub(self.raw_start(s), self.raw_extent(s))?;
+        c.seek(delta)?;
+        Ok(c)
+    }
+
+    pub fn sections(&self) -> &[Section]
+    {
+        &self.sections
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section>
+    {
+        self.sections.iter().find(|s| s.name == name)
     }
 
-    // TODO: Return a Result<(), PEErr>
-    pub unsafe fn init(&mut self)
+    pub fn section_alignment(&self) -> usize
     {
-        // Read the file header offset located at offset 0x3c
-        // Add 0x4 to the read offset to skip the PE Signature
-        let file_header: u32 = (*((self.base_addr + 0x3c) as *const u32)) + 0x4;
-        
-        // The offset for the optional header is at 0x14
-        self.optional_header_offset = file_header + 0x14;
-        
-        // Compute the offset where the offset to the export directory is stored, and retrieve it
-        self.export_directory_offset = *((self.base_addr
-                                            + self.optional_header_offset as usize
-                                            + 0x70) as *const u32);
-        
+        self.section_alignment
+    }
+
+    pub fn file_alignment(&self) -> usize
+    {
+        self.file_alignment
+    }
+
+    // Section mapping the RVA, including the padding up to SectionAlignment
+    pub fn section_for_rva(&self, rva: usize) -> Option<&Section>
+    {
+        self.sections.iter().find(|s| s.contains_rva(rva, self.section_alignment))
+    }
+
+    // File offset of the byte mapped at the RVA
+    // Fails for RVAs only backed by zeroes (past the raw data of their section) or outside of any section
+    pub fn rva_to_offset(&self, rva: usize) -> Result<usize, PEErr>
+    {
+        if rva < self.size_of_headers
+        {
+            return Ok(rva);
+        }
+
+        let s = self.section_for_rva(rva)
+                    .ok_or_else(|| PEErr::failure(format!("RVA {:#x} is not inside any section", rva)))?;
+
+        let delta = rva - s.virtual_address;
+        if delta >= self.raw_extent(s)
+        {
+            return Err(PEErr::failure(format!("RVA {:#x} is not backed by file data", rva)));
+        }
+
+        Ok(self.raw_start(s) + delta)
+    }
+
+    // RVA where the byte at the file offset gets mapped
+    pub fn offset_to_rva(&self, offset: usize) -> Result<usize, PEErr>
+    {
+        if offset < self.size_of_headers
+        {
+            return Ok(offset);
+        }
+
+        self.sections.iter()
+                     .find(|s| offset >= self.raw_start(s) && offset - self.raw_start(s) < self.raw_extent(s))
+                     .map(|s| s.virtual_address + offset - self.raw_start(s))
+                     .ok_or_else(|| PEErr::failure(format!("File offset {:#x} is not mapped by any section", offset)))
+    }
+
+    // RVA of an address of the image, based on base_addr
+    pub fn va_to_rva(&self, va: usize) -> Result<usize, PEErr>
+    {
+        va.checked_sub(self.base_addr)
+          .filter(|&rva| rva < self.size_of_image)
+          .ok_or_else(|| PEErr::failure(format!("Address {:#x} is outside of the image [{:#x}..{:#x}]",
+                                                va, self.base_addr, self.base_addr + self.size_of_image)))
+    }
+
+    fn raw_start(&self, s: &Section) -> usize
+    {
+        s.raw_start(self.file_alignment)
+    }
+
+    fn raw_extent(&self, s: &Section) -> usize
+    {
+        s.raw_extent(self.file_alignment, self.section_alignment)
+    }
+
+    // Content of a section as it is seen once mapped, up to its virtual size
+    // For the File layout, the part not backed by raw data is zero filled
+    // The slice keeps the address of the section (base_addr + RVA)
+    pub fn section_bytes(&self, s: &Section) -> Result<MemSlice<u8>, PEErr>
+    {
+        let size = s.virtual_extent();
+
+        let data = match self.layout
+        {
+            Layout::Mapped => self.at(s.virtual_address)?.read_vec(size)?,
+            Layout::File =>
+            {
+                let backed = self.raw_extent(s).min(size);
+                let mut data = self.image().sub(self.raw_start(s), backed)?.read_vec(backed)?;
+                data.resize(size, 0);
+                data
+            },
+        };
+
+        Ok(MemSlice::at(self.base_addr + s.virtual_address, data))
+    }
+
+    fn named_section(&self, name: &str) -> Result<&Section, PEErr>
+    {
+        self.section(name).ok_or_else(|| PEErr::failure(format!("No section named {}", name)))
+    }
+
+    // Addresses (base_addr + RVA) of every match of the pattern inside the named section
+    pub fn scan_section(&self, section: &str, pattern: &Pattern) -> Result<Vec<usize>, PEErr>
+    {
+        let s = self.named_section(section)?;
+        let data = self.section_bytes(s)?;
+
+        Ok(pattern.find_all(&data).into_iter().map(|i| self.base_addr + s.virtual_address + i).collect())
+    }
+
+    // Addresses of every match of the pattern, in every section
+    pub fn scan(&self, pattern: &Pattern) -> Result<Vec<usize>, PEErr>
+    {
+        let mut found = Vec::new();
+
+        for s in &self.sections
+        {
+            let data = self.section_bytes(s)?;
+            found.extend(pattern.find_all(&data).into_iter().map(|i| self.base_addr + s.virtual_address + i));
+        }
+
+        Ok(found)
+    }
+
+    // Printable ASCII / UTF-16LE strings of the named section, offsets are RVAs
+    pub fn section_strings(&self, section: &str, min_len: usize) -> Result<Vec<FoundString>, PEErr>
+    {
+        self.strings_of(self.named_section(section)?, min_len)
+    }
+
+    // Printable ASCII / UTF-16LE strings of every section, offsets are RVAs
+    pub fn strings(&self, min_len: usize) -> Result<Vec<FoundString>, PEErr>
+    {
+        let mut found = Vec::new();
+
+        for s in &self.sections
+        {
+            found.extend(self.strings_of(s, min_len)?);
+        }
+
+        Ok(found)
+    }
+
+    // Entropy and byte distribution of every section, high entropy hints at packed or encrypted data
+    pub fn section_entropy(&self) -> Result<Vec<SectionEntropy>, PEErr>
+    {
+        let mut report = Vec::with_capacity(self.sections.len());
+
+        for s in &self.sections
+        {
+            let histogram = self.section_bytes(s)?.histogram();
+
+            report.push(SectionEntropy { name: s.name.clone(),
+                                         virtual_address: s.virtual_address,
+                                         size: histogram.total() as usize,
+                                         entropy: histogram.entropy(),
+                                         histogram });
+        }
+
+        Ok(report)
+    }
+
+    fn strings_of(&self, s: &Section, min_len: usize) -> Result<Vec<FoundString>, PEErr>
+    {
+        let data = self.section_bytes(s)?;
+
+        Ok(extract_strings(&data, min_len).into_iter()
+                                          .map(|f| FoundString { offset: s.virtual_address + f.offset,
+                                                                 section: Some(s.name.clone()),
+                                                                 ..f })
+                                          .collect())
+    }
+
+    fn init(&mut self) -> Result<(), PEErr>
+    {
+        // Headers are read through a window over the first page until SizeOfImage is known
+        self.size_of_image = 0x1000;
+        self.size_of_headers = 0x1000;
+        let mut c = MemCursor::new(&self.mem, self.read_base, 0x1000);
+
+        let dos: ImageDosHeader = c.read_struct(Bitness::Bit64)?;
+        if dos.e_magic != IMAGE_DOS_SIGNATURE
+        {
+            return Err(PEErr::failure(format!("Invalid DOS signature {:#06x} at {:#x}", dos.e_magic, self.read_base)));
+        }
+
+        let signature = c.seek(dos.e_lfanew as usize)
+                         .and_then(|c| c.read_u32())
+                         .map_err(|_| PEErr::failure(format!("e_lfanew {:#x} points outside of the headers", dos.e_lfanew)))?;
+        if signature != IMAGE_NT_SIGNATURE
+        {
+            return Err(PEErr::failure(format!("Invalid NT signature {:#010x} at offset {:#x}", signature, dos.e_lfanew)));
+        }
+
+        // The file header follows the PE Signature
+        let file_header = dos.e_lfanew as usize + 0x4;
+        let fh: ImageFileHeader = c.read_struct(Bitness::Bit64)?;
+
+        // The optional header follows the file header
+        // Its magic tells PE32 (data directories at +0x60, 32-bit ImageBase) from PE32+ (+0x70, 64-bit ImageBase)
+        let optional_header_offset = (file_header + ImageFileHeader::size(Bitness::Bit64)) as u32;
+        let opt = OptionalHeader::read(&mut c)?;
+
+        match fh.machine_type().bitness()
+        {
+            None => return Err(PEErr::failure(format!("Unsupported machine {}", fh.machine_type()))),
+            Some(b) if b != opt.bitness() =>
+            {
+                return Err(PEErr::failure(format!("Machine {} does not match the {} optional header (magic {:#x})",
+                                                  fh.machine_type(), opt.bitness(), opt.magic())));
+            },
+            Some(_) => (),
+        }
+
+        if (fh.size_of_optional_header as usize) < opt.size()
+        {
+            return Err(PEErr::failure(format!("SizeOfOptionalHeader {:#x} is smaller than the {:#x} bytes of the optional header",
+                                              fh.size_of_optional_header, opt.size())));
+        }
+
+        let image_base = usize::try_from(opt.image_base())
+                               .map_err(|_| PEErr::failure(format!("ImageBase {:#x} does not fit the host", opt.image_base())))?;
+
+        // SizeOfImage bounds every later read
+        let size_of_image = opt.size_of_image() as usize;
+        let size_of_headers = opt.size_of_headers() as usize;
+
+        // The headers, section table included, must fit in SizeOfHeaders, itself inside the image
+        let section_table = optional_header_offset as usize + fh.size_of_optional_header as usize;
+        let section_table_end = section_table + fh.number_of_sections as usize * ImageSectionHeader::size(Bitness::Bit64);
+
+        if size_of_headers < section_table_end
+        {
+            return Err(PEErr::failure(format!("SizeOfHeaders {:#x} does not cover the section table ending at {:#x}",
+                                              size_of_headers, section_table_end)));
+        }
+
+        if size_of_headers > size_of_image
+        {
+            return Err(PEErr::failure(format!("SizeOfHeaders {:#x} is larger than SizeOfImage {:#x}", size_of_headers, size_of_image)));
+        }
+
+        // Every data directory must lie inside the image
+        // The security directory is the exception: its address is a file offset, the certificates are not mapped
+        for (idx, dir) in opt.data_directories().iter().enumerate()
+        {
+            if idx == DataDirectory::Security as usize || (dir.virtual_address == 0 && dir.size == 0)
+            {
+                continue;
+            }
+
+            let end = (dir.virtual_address as usize).checked_add(dir.size as usize);
+            if !matches!(end, Some(end) if end <= size_of_image)
+            {
+                return Err(PEErr::failure(format!("Data directory {} [{:#x}, +{:#x}] is outside of the image (SizeOfImage {:#x})",
+                                                  idx, dir.virtual_address, dir.size, size_of_image)));
+            }
+        }
+
+        let export_directory_offset = opt.data_directory(DataDirectory::Export).map(|d| d.virtual_address).unwrap_or(0);
+
+        // The section table follows the optional header
+        let mut c = MemCursor::new(&self.mem, self.read_base, size_of_headers);
+        c.seek(section_table)?;
+
+        // Every section, once mapped, must lie inside the image
+        // Its content is read up to the virtual size, which a File layout does not bound
+        let mut sections = Vec::with_capacity(fh.number_of_sections as usize);
+        for _ in 0..fh.number_of_sections
+        {
+            let sh: ImageSectionHeader = c.read_struct(Bitness::Bit64)?;
+            let s = Section::from_header(&sh);
+
+            let end = s.virtual_address.checked_add(s.mapped_extent(opt.section_alignment() as usize));
+            if !matches!(end, Some(end) if end <= size_of_image)
+            {
+                return Err(PEErr::failure(format!("Section {} [{:#x}, +{:#x}] is outside of the image (SizeOfImage {:#x})",
+                                                  s.name, s.virtual_address, s.virtual_extent(), size_of_image)));
+            }
+
+            sections.push(s);
+        }
+
+        self.optional_header_offset = optional_header_offset;
+        self.bitness = opt.bitness();
+        self.size_of_image = size_of_image;
+        self.size_of_headers = size_of_headers;
+        self.sections = sections;
+        self.section_alignment = opt.section_alignment() as usize;
+        self.file_alignment = opt.file_alignment() as usize;
+        self.export_directory_offset = export_directory_offset;
+
+        if self.layout == Layout::File
+        {
+            self.base_addr = image_base;
+        }
+
+        // Nothing more to parse without exports
+        if !self.has_exports()
+        {
+            return Ok(());
+        }
+
         // Compute a final absolute address to the export directory
         self.export_directory_addr = self.base_addr + self.export_directory_offset as usize;
 
+        // The directory and its tables are read once, each name is then read in bulk
+        let exp = self.export_directory()?;
+        let number_of_names = exp.number_of_names as usize;
+
         // Ordinal Base:
-        self.exp_dir_base = *((self.export_directory_addr + 0x10) as *const u8) as usize;
+        self.exp_dir_base = exp.base as usize;
+
+        let names = self.export_table(exp.address_of_names as usize, number_of_names, 4, "names")?;
+        let ordinals = self.export_table(exp.address_of_name_ordinals as usize, number_of_names, 2, "name ordinals")?;
 
         // Populate the array of function names
-        for idx in 0..self.number_of_names()
+        // A bad name only invalidates its own entry, it is recorded and the parsing goes on
+        for (idx, rva) in names.chunks_exact(4).enumerate()
         {
-            self.fnames.push(self.fname_from_index(idx as usize)); 
+            let rva = u32::from_le_bytes([rva[0], rva[1], rva[2], rva[3]]) as usize;
+
+            match self.name_at(rva)
+            {
+                Ok(fname) => self.fnames.push(Some(String::from_utf8_lossy(&fname).to_string())),
+                Err(e) =>
+                {
+                    self.export_issues.push(format!("Export name #{}: {}", idx, e.message));
+                    self.fnames.push(None);
+                },
+            }
         }
 
         // Populate the array of ordinals
-        
-        for idx in 0..self.number_of_names()
+        for ord in ordinals.chunks_exact(2)
         {
-            self.fnames_ordinals.push(self.ford_from_index(idx as usize) + self.exp_dir_base);
+            self.fnames_ordinals.push(u16::from_le_bytes([ord[0], ord[1]]) as usize + self.exp_dir_base);
         }
 
         // TODO: Replace by a match to handle the PEName::Is(x) case
-        if self.export_directory_offset != 0x0 && self.name == PEName::Empty
+        if self.name == PEName::Empty
         {
-            self.name = match self.set_export_name()
+            match self.set_export_name(exp.name)
             {
-                Ok(name) => name,
-                Err(e)  => { 
-                    eprintln!("{}", e.message);
-                    PEName::Empty
-                }
+                Ok(name) => self.name = name,
+                Err(e) => self.export_issues.push(format!("Export DLL name: {}", e.message)),
             }
         }
+
+        Ok(())
     }
 
-    pub unsafe fn get_export_directory_ptr(&self) -> *const usize
+    pub fn get_export_directory_ptr(&self) -> *const usize
     {
         (self.base_addr + self.export_directory_offset as usize) as *const usize
     }
 
+    pub fn size_of_image(&self) -> usize
+    {
+        self.size_of_image
+    }
+
+    pub fn dos_header(&self) -> Result<ImageDosHeader, PEErr>
+    {
+        self.at(0)?.read_struct(Bitness::Bit64)
+    }
+
+    // IMAGE_FILE_HEADER, machine, timestamp, characteristics...
+    pub fn file_header(&self) -> Result<ImageFileHeader, PEErr>
+    {
+        self.at(self.file_header_offset())?.read_struct(Bitness::Bit64)
+    }
+
+    // PE32 or PE32+ optional header, image base, entry point, alignments, data directories...
+    pub fn optional_header(&self) -> Result<OptionalHeader, PEErr>
+    {
+        OptionalHeader::read(&mut self.at(self.optional_header_offset as usize)?)
+    }
+
+    // None when the image has no such entry
+    pub fn data_directory(&self, entry: DataDirectory) -> Result<Option<ImageDataDirectory>, PEErr>
+    {
+        Ok(self.optional_header()?.data_directory(entry).cloned())
+    }
+
+    fn file_header_offset(&self) -> usize
+    {
+        self.optional_header_offset as usize - ImageFileHeader::size(Bitness::Bit64)
+    }
+
+    pub fn has_exports(&self) -> bool
+    {
+        self.export_directory_offset != 0
+    }
+
+    // Fails when the image has no export directory, RVA 0 would otherwise be read as one
+    pub fn export_directory(&self) -> Result<ImageExportDirectory, PEErr>
+    {
+        if !self.has_exports()
+        {
+            return Err(PEErr::failure("Image has no export directory"));
+        }
+
+        self.at(self.export_directory_offset as usize)?.read_struct(Bitness::Bit64)
+    }
+
+    // DLLs and functions imported by the image, empty when it has no import directory
+    // A bad descriptor or thunk is recorded in the issues, the entries already parsed are kept
+    pub fn imports(&self) -> Result<Imports, PEErr>
+    {
+        let mut imports = Imports::default();
+
+        let dir = match self.data_directory(DataDirectory::Import)?
+        {
+            Some(dir) if dir.virtual_address != 0 => dir,
+            _ => return Ok(imports),
+        };
+
+        let descriptor_size = ImageImportDescriptor::size(Bitness::Bit64);
+
+        for idx in 0..
+        {
+            // Without its zeroed terminator, the array stops at the end of the readable data
+            let desc: ImageImportDescriptor = match self.at(dir.virtual_address as usize + idx * descriptor_size)
+                                                        .and_then(|mut c| c.read_struct(Bitness::Bit64))
+            {
+                Ok(desc) => desc,
+                Err(e) =>
+                {
+                    imports.issues.push(format!("Import descriptor #{}: {}", idx, e.message));
+                    break;
+                },
+            };
+
+            // The array ends with a zeroed descriptor
+            if desc.name == 0 && desc.first_thunk == 0
+            {
+                break;
+            }
+
+            let name = match self.name_at(desc.name as usize)
+            {
+                Ok(name) => String::from_utf8_lossy(&name).to_string(),
+                Err(e) =>
+                {
+                    imports.issues.push(format!("Import descriptor #{} name: {}", idx, e.message));
+                    continue;
+                },
+            };
+
+            let functions = self.import_thunks(&desc, &name, &mut imports.issues);
+
+            imports.dlls.push(ImportedDll { name,
+                                            time_date_stamp: desc.time_date_stamp,
+                                            forwarder_chain: desc.forwarder_chain,
+                                            original_first_thunk: desc.original_first_thunk,
+                                            first_thunk: desc.first_thunk,
+                                            functions });
+        }
+
+        Ok(imports)
+    }
+
+    // Walk the lookup table of a descriptor, pairing each thunk with its IAT slot
+    // The walk stops at the first unreadable thunk, the functions before it are kept
+    fn import_thunks(&self, desc: &ImageImportDescriptor, dll: &str, issues: &mut Vec<String>) -> Vec<ImportedFunction>
+    {
+        let mut functions = Vec::new();
+
+        for idx in 0..
+        {
+            match self.import_thunk(desc, idx)
+            {
+                Ok(Some(function)) => functions.push(function),
+                Ok(None) => break,
+                Err(e) =>
+                {
+                    issues.push(format!("Import thunk #{} of {}: {}", idx, dll, e.message));
+                    break;
+                },
+            }
+        }
+
+        functions
+    }
+
+    // Thunk #idx of a descriptor, None at the null thunk ending the table
+    fn import_thunk(&self, desc: &ImageImportDescriptor, idx: usize) -> Result<Option<ImportedFunction>, PEErr>
+    {
+        // Old linkers leave OriginalFirstThunk empty, the names are then only found in the IAT before binding
+        // Once the IAT is bound or filled by the loader, its slots hold addresses and the thunks can't be decoded
+        let lookup = if desc.original_first_thunk != 0 { desc.original_first_thunk } else { desc.first_thunk } as usize;
+        let decodable = desc.original_first_thunk != 0 || (self.layout == Layout::File && desc.time_date_stamp == 0);
+        let ptr_size = self.bitness.ptr_size();
+
+        let mut c = self.at(lookup + idx * ptr_size)?;
+
+        // Thunks are pointer sized, the ordinal flag is their top bit
+        let (value, by_ordinal) = match self.bitness
+        {
+            Bitness::Bit32 =>
+            {
+                let v = c.read_u32()?;
+                (v as u64, v & IMAGE_ORDINAL_FLAG32 != 0)
+            },
+            Bitness::Bit64 =>
+            {
+                let v = c.read_u64()?;
+                (v, v & IMAGE_ORDINAL_FLAG64 != 0)
+            },
+        };
+
+        if value == 0
+        {
+            return Ok(None);
+        }
+
+        let thunk = if !decodable
+        {
+            ImportThunk::Unknown
+        }
+        else if by_ordinal
+        {
+            ImportThunk::ByOrdinal(value as u16)
+        }
+        else
+        {
+            // RVA of an IMAGE_IMPORT_BY_NAME: the hint, then the null terminated name
+            let rva = (value & 0x7fff_ffff) as usize;
+            let hint = self.at(rva)?.read_u16()?;
+            let name = String::from_utf8_lossy(&self.name_at(rva + 2)?).to_string();
+
+            ImportThunk::ByName { hint, name }
+        };
+
+        let iat_rva = desc.first_thunk as usize + idx * ptr_size;
+
+        // Once mapped, the loader has replaced the IAT slot by the address of the function
+        // A bound IAT already holds the addresses in the file
+        let resolved = if self.layout == Layout::Mapped || desc.time_date_stamp != 0
+        {
+            Some(self.at(iat_rva)?.read_ptr(self.bitness)?)
+        }
+        else
+        {
+            None
+        };
+
+        Ok(Some(ImportedFunction { thunk, iat_rva, resolved }))
+    }
+
     // Set the name of the PE based on the exported name
-    // in the export directory
-    fn set_export_name(&self) -> Result<PEName, PEErr>
+    // in the export directory, invalid UTF-8 is replaced by U+FFFD
+    fn set_export_name(&self, name_offset: u32) -> Result<PEName, PEErr>
     {
-        let name_addr: usize;
-        let name: Vec<u8>;
+        let name = self.name_at(name_offset as usize)?;
 
-        unsafe
+        Ok(PEName::Is(String::from_utf8_lossy(&name).to_string()))
+    }
+
+    // Export entries skipped during the parsing (unreadable names...), empty for a sane image
+    pub fn export_issues(&self) -> &[String]
+    {
+        &self.export_issues
+    }
+
+    // Read one of the export tables, its entry count comes from the file and is checked before allocating
+    fn export_table(&self, rva: usize, count: usize, entry_size: usize, what: &str) -> Result<Vec<u8>, PEErr>
+    {
+        let mut c = self.at(rva)?;
+
+        match count.checked_mul(entry_size)
         {
-            let name_offset = *((self.base_addr + self.export_directory_offset as usize + 0xC) as *const u32);
-            name_addr = self.base_addr + name_offset as usize;
+            Some(size) if size <= c.remaining() => c.read_vec(size),
+            _ => Err(PEErr::failure(format!("Export {} table at RVA {:#x} cannot hold {} entries", what, rva, count))),
         }
+    }
+
+    // Read the null terminated name located at rva
+    // Bounded by MAX_NAME_LEN and by the end of the image (or of the section for the File layout)
+    fn name_at(&self, rva: usize) -> Result<Vec<u8>, PEErr>
+    {
+        let c = self.at(rva)?;
+        let available = c.remaining();
 
-        (name, _) = read_null!(name_addr as usize, u8);
-        
-        match String::from_utf8(name)
+        match read_cstr_limited(&self.mem, c.addr(), available.min(MAX_NAME_LEN))?
         {
-            Ok(name) => return Ok( PEName::Is(name) ),
-            Err(_) => return Err( PEErr 
-                                  { 
-                                    status: ErrState::Failure, 
-                                    message: String::from("Failed to decode the name from export directory (UTF8 fail)") 
-                                  } ),
+            NullRead::Terminated(name) => Ok(name),
+            NullRead::Truncated(_) if available > MAX_NAME_LEN =>
+                Err(PEErr::failure(format!("Name at RVA {:#x} is longer than {} bytes", rva, MAX_NAME_LEN))),
+            NullRead::Truncated(_) =>
+                Err(PEErr::failure(format!("Name at RVA {:#x} is not null terminated before the end of the readable data", rva))),
         }
     }
 
@@ -170,161 +852,131 @@ impl PEImage
         self.name = PEName::Is(String::from(new_name));
     }
 
-    pub unsafe fn number_of_func(&self) -> u32
+    pub fn number_of_func(&self) -> Result<u32, PEErr>
     {
-        *((self.export_directory_addr + 0x14) as *const u32) 
+        Ok(self.export_directory()?.number_of_functions)
     }
 
-    pub unsafe fn number_of_names(&self) -> u32
+    pub fn number_of_names(&self) -> Result<u32, PEErr>
     {
-        *((self.export_directory_addr + 0x18) as *const u32) 
+        Ok(self.export_directory()?.number_of_names)
     }
 
-    pub unsafe fn funcs_offset(&self) -> usize
+    pub fn funcs_offset(&self) -> Result<usize, PEErr>
     {
-        *((self.export_directory_addr + 0x1c) as *const u32) as usize
+        Ok(self.export_directory()?.address_of_functions as usize)
     }
-    
-    pub unsafe fn names_offset(&self) -> usize
+
+    pub fn names_offset(&self) -> Result<usize, PEErr>
     {
-        *((self.export_directory_addr + 0x20) as *const u32) as usize
+        Ok(self.export_directory()?.address_of_names as usize)
     }
 
-    pub unsafe fn ordinals_offset(&self) -> usize
+    pub fn ordinals_offset(&self) -> Result<usize, PEErr>
     {
-        *((self.export_directory_addr + 0x24) as *const u32) as usize
+        Ok(self.export_directory()?.address_of_name_ordinals as usize)
     }
 
-    pub fn syscall_from_name(&self, fname: &str) -> usize
+    pub fn syscall_from_name(&self, fname: &str) -> Result<usize, PEErr>
     {
-        let idx = self.idx_from_name(fname);
-        if idx < 0
-        {
-            panic!("Can't find index for {}", fname);
-        }
-
-        let idx = idx as usize;
+        let idx = self.idx_from_name(fname)
+                      .ok_or_else(|| PEErr::failure(format!("No export named {}", fname)))?;
 
         let ord = self.fnames_ordinals[idx];
-        let faddr = self.faddr_from_ord(ord);
+        let rva = self.rva_from_ord(ord)?;
 
-        unsafe
+        // x64 stubs start with mov r10, rcx (4C 8B D1) before mov eax, imm32 (B8)
+        // x86 stubs start directly with mov eax, imm32
+        let prologue: &[u8] = match self.bitness
         {
-            crate::memory::read_mem::<u8>(faddr + 4, 1, 1).stub[0] as usize
+            Bitness::Bit64 => &[0x4c, 0x8b, 0xd1, 0xb8],
+            Bitness::Bit32 => &[0xb8],
+        };
+
+        let mut c = self.at(rva)?;
+        let stub = c.read_vec(prologue.len())?;
+        if stub != prologue
+        {
+            return Err(PEErr::failure(format!("{} is not a syscall stub: starts with {:02x?} instead of {:02x?}",
+                                              fname, stub, prologue)));
         }
+
+        Ok(c.read_u32()? as usize)
     }
 
-    pub fn fname_from_index(&self, index: usize) -> String
+    pub fn fname_from_index(&self, index: usize) -> Result<String, PEErr>
     {
-        let name_addr: usize;
+        let name_offset = self.at(self.names_offset()? + (index * 4))?.read_u32()?;
 
-        unsafe
-        {
-            name_addr = self.base_addr
-                        + *((self.base_addr + self.names_offset() 
-                        + (index * 4)) as *const u32) as usize;
-        }
-
-        let (name, _) = read_null!(name_addr as usize, u8);
+        let name = self.name_at(name_offset as usize)?;
         let name = String::from_utf8_lossy(&name).to_string(); //.unwrap();
 
-        name
+        Ok(name)
     }
 
-    pub fn ford_from_index(&self, index: usize) -> usize
+    pub fn ford_from_index(&self, index: usize) -> Result<usize, PEErr>
     {
         let size = 2;
-        let ret: usize;
 
-        unsafe
-        {
-            let ord_addr = self.base_addr + self.ordinals_offset(); 
-            let ord = crate::memory::read_mem::<u8>(ord_addr + size * index, size, 1);
-            ret = u16::from_le_bytes(ord.stub.try_into().expect("failed conversion")) as usize; 
-        }
+        let ord = self.at(self.ordinals_offset()? + size * index)?.read_u16()?;
 
-        ret
+        Ok(ord as usize)
     }
-    
-    pub fn faddr_from_ord(&self, ord: usize) -> usize
+
+    pub fn faddr_from_ord(&self, ord: usize) -> Result<usize, PEErr>
     {
-            let rva = self.rva_from_ord(ord);
+            let rva = self.rva_from_ord(ord)?;
 
-            (self.base_addr + rva) as usize
+            Ok(self.base_addr + rva)
     }
 
-    // TODO: handle proper error instead of -1
-    pub fn idx_from_name(&self, fname: &str) -> isize
+    // Index of the name in the export name table
+    pub fn idx_from_name(&self, fname: &str) -> Option<usize>
     {
-        let mut idx: isize = 0;
-
-        for name in &self.fnames
-        {
-            if name == fname
-            {
-                return idx;
-            }
+        self.fnames.iter().position(|name| name.as_deref() == Some(fname))
+    }
 
-            idx += 1;
-        }
+    // Same as idx_from_name, ignoring case the way Windows compares names
+    pub fn idx_from_name_ignore_case(&self, fname: &str) -> Option<usize>
+    {
+        let fname = WideString::from(fname);
 
-        -1
+        self.fnames.iter()
+                   .position(|name| name.as_deref().is_some_and(|name| WideString::from(name).eq_ignore_case(&fname)))
     }
 
-    pub fn rva_from_ord(&self, ord: usize) -> usize
+    pub fn rva_from_ord(&self, ord: usize) -> Result<usize, PEErr>
     {
-        unsafe
-        {
-            *((self.base_addr 
-               + self.funcs_offset() 
-               + ((ord - self.exp_dir_base) * 4)) 
-               as *const u32) as usize
-        }
+        let idx = ord.checked_sub(self.exp_dir_base)
+                     .ok_or_else(|| PEErr::failure(format!("Ordinal {} is below the ordinal base {}", ord, self.exp_dir_base)))?;
+
+        Ok(self.at(self.funcs_offset()? + (idx * 4))?.read_u32()? as usize)
     }
 
-    pub unsafe fn funcs_addr(&self) -> usize
+    pub fn funcs_addr(&self) -> Result<usize, PEErr>
     {
-        self.base_addr +  self.funcs_offset() as usize
+        Ok(self.base_addr + self.funcs_offset()?)
     }
 
-    // TODO: Rewrite
-    pub unsafe fn find_func_addr(&self, find: &str) -> (usize, usize)
+    // Address and ordinal of the function exported under the given name
+    pub fn find_func_addr(&self, find: &str) -> Result<(usize, usize), PEErr>
     {
-        let mut ord = 1;
+        let idx = self.idx_from_name(find)
+                      .ok_or_else(|| PEErr::failure(format!("No export named {}", find)))?;
 
-        // Compute ordinal for given function
-        for _ in 0..(self.number_of_names())
-        {
-            let name = self.fname_from_index(ord);
-            
-            let found = String::from(name);
-            let looking_for = String::from(find);
-
-            if found == looking_for
-            {
-                println!("Found {}", found);
-                break
-            }
-
-            ord += 1;
-        }
-
-        // Use ordinal to get the offset of function
-        // And combine it with base address
-        let offset = *((self.base_addr + self.funcs_offset() + (ord * 4) ) as *const u32);
-        let addr: usize = offset as usize + self.base_addr;
+        let ord = self.fnames_ordinals[idx];
 
-        (addr, ord)
+        Ok((self.faddr_from_ord(ord)?, ord))
     }
 }
 
 /// Iterator implementation
 /// Iterate through the ordinal
 /// Enable easy iteration over functions / addresses....
-impl<'a> IntoIterator for &'a PEImage
+impl<'a, M: MemorySource> IntoIterator for &'a PEImage<M>
 {
     type Item = (usize, usize);
-    type IntoIter = PEImageIntoIterator<'a>;
+    type IntoIter = PEImageIntoIterator<'a, M>;
 
     fn into_iter(self) -> Self::IntoIter {
         PEImageIntoIterator {
@@ -334,17 +986,17 @@ impl<'a> IntoIterator for &'a PEImage
     }
 }
 
-pub struct PEImageIntoIterator<'a>
+pub struct PEImageIntoIterator<'a, M: MemorySource = LiveMemory>
 {
-    pe: &'a PEImage,
+    pe: &'a PEImage<M>,
     ord_idx: usize,
 }
 
-impl<'a> Iterator for PEImageIntoIterator<'a>
+impl<M: MemorySource> Iterator for PEImageIntoIterator<'_, M>
 {
     type Item = (usize, usize);
 
-    fn next(&mut self) -> Option<Self::Item> 
+    fn next(&mut self) -> Option<Self::Item>
     {
         if self.ord_idx == (self.pe.fnames_ordinals.len())
         {
@@ -360,13 +1012,10 @@ impl<'a> Iterator for PEImageIntoIterator<'a>
 }
 
 /// Display trait implementation
-impl fmt::Display for PEImage 
+impl<M: MemorySource> fmt::Display for PEImage<M>
 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result 
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
     {
-        unsafe
-        { 
-            write!(f, "[- {} -]\nBase Addr: {:#x}\nFunc num: {}\nName num: {}\nOptional Header Offset: {:#x}\nExport Directory Addr: {:#x}\nExport Directory Offset: {:#x}\nFunc Offset: {:#x}\nAddr of funcs: {:#x}", self.name, self.base_addr, self.number_of_func(), self.number_of_names(), self.optional_header_offset, self.export_directory_addr, self.export_directory_offset, self.funcs_offset(), self.funcs_addr())
-        }
+        write!(f, "[- {} -]\nBase Addr: {:#x}\nFunc num: {}\nName num: {}\nOptional Header Offset: {:#x}\nExport Directory Addr: {:#x}\nExport Directory Offset: {:#x}\nFunc Offset: {:#x}\nAddr of funcs: {:#x}", self.name, self.base_addr, self.number_of_func().unwrap_or_default(), self.number_of_names().unwrap_or_default(), self.optional_header_offset, self.export_directory_addr, self.export_directory_offset, self.funcs_offset().unwrap_or_default(), self.funcs_addr().unwrap_or_default())
     }
 }
@@ -0,0 +1,781 @@
+use crate::err::*;
+use crate::memory::{MemorySource, MemCursor, Bitness};
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+
+pub const IMAGE_DOS_SIGNATURE: u16 = 0x5a4d;            // MZ
+pub const IMAGE_NT_SIGNATURE: u32 = 0x0000_4550;        // PE\0\0
+pub const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+pub const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+pub const IMAGE_NUMBEROF_DIRECTORY_ENTRIES: usize = 16;
+pub const IMAGE_ORDINAL_FLAG32: u32 = 0x8000_0000;
+pub const IMAGE_ORDINAL_FLAG64: u64 = 0x8000_0000_0000_0000;
+
+// =================================================== Structures
+
+nt_struct!
+{
+    // IMAGE_DOS_HEADER
+    pub struct ImageDosHeader : ImageDosHeaderOffsets
+    {
+        e_magic:    u16         = 0x00, 0x00;
+        e_cblp:     u16         = 0x02, 0x02;
+        e_cp:       u16         = 0x04, 0x04;
+        e_crlc:     u16         = 0x06, 0x06;
+        e_cparhdr:  u16         = 0x08, 0x08;
+        e_minalloc: u16         = 0x0a, 0x0a;
+        e_maxalloc: u16         = 0x0c, 0x0c;
+        e_ss:       u16         = 0x0e, 0x0e;
+        e_sp:       u16         = 0x10, 0x10;
+        e_csum:     u16         = 0x12, 0x12;
+        e_ip:       u16         = 0x14, 0x14;
+        e_cs:       u16         = 0x16, 0x16;
+        e_lfarlc:   u16         = 0x18, 0x18;
+        e_ovno:     u16         = 0x1a, 0x1a;
+        e_res:      [u16; 4]    = 0x1c, 0x1c;
+        e_oemid:    u16         = 0x24, 0x24;
+        e_oeminfo:  u16         = 0x26, 0x26;
+        e_res2:     [u16; 10]   = 0x28, 0x28;
+        e_lfanew:   u32         = 0x3c, 0x3c;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_FILE_HEADER, follows the PE signature
+    pub struct ImageFileHeader : ImageFileHeaderOffsets
+    {
+        machine:                    u16 = 0x00, 0x00;
+        number_of_sections:         u16 = 0x02, 0x02;
+        time_date_stamp:            u32 = 0x04, 0x04;
+        pointer_to_symbol_table:    u32 = 0x08, 0x08;
+        number_of_symbols:          u32 = 0x0c, 0x0c;
+        size_of_optional_header:    u16 = 0x10, 0x10;
+        characteristics:            u16 = 0x12, 0x12;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_DATA_DIRECTORY
+    pub struct ImageDataDirectory : ImageDataDirectoryOffsets
+    {
+        virtual_address:    u32 = 0x0, 0x0;
+        size:               u32 = 0x4, 0x4;
+    }
+}
+
+// The layout of the optional header follows its magic, not the target pointer width:
+// both columns of the two structures are the same
+
+nt_struct!
+{
+    // IMAGE_OPTIONAL_HEADER32, PE32 images
+    pub struct ImageOptionalHeader32 : ImageOptionalHeader32Offsets
+    {
+        magic:                          u16                         = 0x00, 0x00;
+        major_linker_version:           u8                          = 0x02, 0x02;
+        minor_linker_version:           u8                          = 0x03, 0x03;
+        size_of_code:                   u32                         = 0x04, 0x04;
+        size_of_initialized_data:       u32                         = 0x08, 0x08;
+        size_of_uninitialized_data:     u32                         = 0x0c, 0x0c;
+        address_of_entry_point:         u32                         = 0x10, 0x10;
+        base_of_code:                   u32                         = 0x14, 0x14;
+        base_of_data:                   u32                         = 0x18, 0x18;
+        image_base:                     u32                         = 0x1c, 0x1c;
+        section_alignment:              u32                         = 0x20, 0x20;
+        file_alignment:                 u32                         = 0x24, 0x24;
+        major_operating_system_version: u16                         = 0x28, 0x28;
+        minor_operating_system_version: u16                         = 0x2a, 0x2a;
+        major_image_version:            u16                         = 0x2c, 0x2c;
+        minor_image_version:            u16                         = 0x2e, 0x2e;
+        major_subsystem_version:        u16                         = 0x30, 0x30;
+        minor_subsystem_version:        u16                         = 0x32, 0x32;
+        win32_version_value:            u32                         = 0x34, 0x34;
+        size_of_image:                  u32                         = 0x38, 0x38;
+        size_of_headers:                u32                         = 0x3c, 0x3c;
+        check_sum:                      u32                         = 0x40, 0x40;
+        subsystem:                      u16                         = 0x44, 0x44;
+        dll_characteristics:            u16                         = 0x46, 0x46;
+        size_of_stack_reserve:          u32                         = 0x48, 0x48;
+        size_of_stack_commit:           u32                         = 0x4c, 0x4c;
+        size_of_heap_reserve:           u32                         = 0x50, 0x50;
+        size_of_heap_commit:            u32                         = 0x54, 0x54;
+        loader_flags:                   u32                         = 0x58, 0x58;
+        number_of_rva_and_sizes:        u32                         = 0x5c, 0x5c;
+        data_directory:                 [ImageDataDirectory; 16]    = 0x60, 0x60;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_OPTIONAL_HEADER64, PE32+ images
+    pub struct ImageOptionalHeader64 : ImageOptionalHeader64Offsets
+    {
+        magic:                          u16                         = 0x00, 0x00;
+        major_linker_version:           u8                          = 0x02, 0x02;
+        minor_linker_version:           u8                          = 0x03, 0x03;
+        size_of_code:                   u32                         = 0x04, 0x04;
+        size_of_initialized_data:       u32                         = 0x08, 0x08;
+        size_of_uninitialized_data:     u32                         = 0x0c, 0x0c;
+        address_of_entry_point:         u32                         = 0x10, 0x10;
+        base_of_code:                   u32                         = 0x14, 0x14;
+        image_base:                     u64                         = 0x18, 0x18;
+        section_alignment:              u32                         = 0x20, 0x20;
+        file_alignment:                 u32                         = 0x24, 0x24;
+        major_operating_system_version: u16                         = 0x28, 0x28;
+        minor_operating_system_version: u16                         = 0x2a, 0x2a;
+        major_image_version:            u16                         = 0x2c, 0x2c;
+        minor_image_version:            u16                         = 0x2e, 0x2e;
+        major_subsystem_version:        u16                         = 0x30, 0x30;
+        minor_subsystem_version:        u16                         = 0x32, 0x32;
+        win32_version_value:            u32                         = 0x34, 0x34;
+        size_of_image:                  u32                         = 0x38, 0x38;
+        size_of_headers:                u32                         = 0x3c, 0x3c;
+        check_sum:                      u32                         = 0x40, 0x40;
+        subsystem:                      u16                         = 0x44, 0x44;
+        dll_characteristics:            u16                         = 0x46, 0x46;
+        size_of_stack_reserve:          u64                         = 0x48, 0x48;
+        size_of_stack_commit:           u64                         = 0x50, 0x50;
+        size_of_heap_reserve:           u64                         = 0x58, 0x58;
+        size_of_heap_commit:            u64                         = 0x60, 0x60;
+        loader_flags:                   u32                         = 0x68, 0x68;
+        number_of_rva_and_sizes:        u32                         = 0x6c, 0x6c;
+        data_directory:                 [ImageDataDirectory; 16]    = 0x70, 0x70;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_SECTION_HEADER
+    pub struct ImageSectionHeader : ImageSectionHeaderOffsets
+    {
+        name:                       [u8; 8] = 0x00, 0x00;
+        virtual_size:               u32     = 0x08, 0x08;
+        virtual_address:            u32     = 0x0c, 0x0c;
+        size_of_raw_data:           u32     = 0x10, 0x10;
+        pointer_to_raw_data:        u32     = 0x14, 0x14;
+        pointer_to_relocations:     u32     = 0x18, 0x18;
+        pointer_to_linenumbers:     u32     = 0x1c, 0x1c;
+        number_of_relocations:      u16     = 0x20, 0x20;
+        number_of_linenumbers:      u16     = 0x22, 0x22;
+        characteristics:            u32     = 0x24, 0x24;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_EXPORT_DIRECTORY
+    pub struct ImageExportDirectory : ImageExportDirectoryOffsets
+    {
+        characteristics:            u32 = 0x00, 0x00;
+        time_date_stamp:            u32 = 0x04, 0x04;
+        major_version:              u16 = 0x08, 0x08;
+        minor_version:              u16 = 0x0a, 0x0a;
+        name:                       u32 = 0x0c, 0x0c;
+        base:                       u32 = 0x10, 0x10;
+        number_of_functions:        u32 = 0x14, 0x14;
+        number_of_names:            u32 = 0x18, 0x18;
+        address_of_functions:       u32 = 0x1c, 0x1c;
+        address_of_names:           u32 = 0x20, 0x20;
+        address_of_name_ordinals:   u32 = 0x24, 0x24;
+    }
+}
+
+nt_struct!
+{
+    // IMAGE_IMPORT_DESCRIPTOR, the import directory is an array of them ended by a zeroed one
+    pub struct ImageImportDescriptor : ImageImportDescriptorOffsets
+    {
+        original_first_thunk:       u32 = 0x00, 0x00;   // RVA of the lookup table (INT)
+        time_date_stamp:            u32 = 0x04, 0x04;   // -1 when the IAT is bound
+        forwarder_chain:            u32 = 0x08, 0x08;
+        name:                       u32 = 0x0c, 0x0c;
+        first_thunk:                u32 = 0x10, 0x10;   // RVA of the IAT
+    }
+}
+
+impl ImageFileHeader
+{
+    pub fn machine_type(&self) -> Machine
+    {
+        Machine::from(self.machine)
+    }
+
+    pub fn flags(&self) -> FileCharacteristics
+    {
+        FileCharacteristics(self.characteristics)
+    }
+}
+
+// =================================================== Optional header
+
+// Optional header of either layout, selected by its magic
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionalHeader
+{
+    Pe32(ImageOptionalHeader32),
+    Pe64(ImageOptionalHeader64),
+}
+
+// Index of each entry of the data directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataDirectory
+{
+    Export = 0,
+    Import = 1,
+    Resource = 2,
+    Exception = 3,
+    Security = 4,
+    BaseReloc = 5,
+    Debug = 6,
+    Architecture = 7,
+    GlobalPtr = 8,
+    Tls = 9,
+    LoadConfig = 10,
+    BoundImport = 11,
+    Iat = 12,
+    DelayImport = 13,
+    ComDescriptor = 14,
+    Reserved = 15,
+}
+
+// Same field, whatever the layout
+macro_rules! opt_field
+{
+    ($self:ident, $field:ident) =>
+    {
+        match $self
+        {
+            OptionalHeader::Pe32(h) => h.$field,
+            OptionalHeader::Pe64(h) => h.$field,
+        }
+    };
+}
+
+impl OptionalHeader
+{
+    // Read the optional header at the position of the cursor, branching on its magic
+    pub fn read<M: MemorySource + ?Sized>(c: &mut MemCursor<'_, M>) -> Result<OptionalHeader, PEErr>
+    {
+        match c.clone().read_u16()?
+        {
+            IMAGE_NT_OPTIONAL_HDR32_MAGIC => Ok(OptionalHeader::Pe32(c.read_struct(Bitness::Bit32)?)),
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => Ok(OptionalHeader::Pe64(c.read_struct(Bitness::Bit64)?)),
+            magic => Err(PEErr::failure(format!("Unknown optional header magic {:#x}", magic))),
+        }
+    }
+
+    pub fn magic(&self) -> u16
+    {
+        opt_field!(self, magic)
+    }
+
+    // Pointer width of the image, PE32 is 32-bit and PE32+ is 64-bit
+    pub fn bitness(&self) -> Bitness
+    {
+        match self
+        {
+            OptionalHeader::Pe32(_) => Bitness::Bit32,
+            OptionalHeader::Pe64(_) => Bitness::Bit64,
+        }
+    }
+
+    // Number of bytes of the header up to the end of the data directories actually present
+    pub fn size(&self) -> usize
+    {
+        let directories = match self
+        {
+            OptionalHeader::Pe32(_) => ImageOptionalHeader32::OFFSETS32.data_directory,
+            OptionalHeader::Pe64(_) => ImageOptionalHeader64::OFFSETS64.data_directory,
+        };
+
+        directories + self.number_of_rva_and_sizes().min(IMAGE_NUMBEROF_DIRECTORY_ENTRIES) * 8
+    }
+
+    pub fn image_base(&self) -> u64
+    {
+        match self
+        {
+            OptionalHeader::Pe32(h) => h.image_base as u64,
+            OptionalHeader::Pe64(h) => h.image_base,
+        }
+    }
+
+    pub fn address_of_entry_point(&self) -> u32
+    {
+        opt_field!(self, address_of_entry_point)
+    }
+
+    pub fn base_of_code(&self) -> u32
+    {
+        opt_field!(self, base_of_code)
+    }
+
+    pub fn section_alignment(&self) -> u32
+    {
+        opt_field!(self, section_alignment)
+    }
+
+    pub fn file_alignment(&self) -> u32
+    {
+        opt_field!(self, file_alignment)
+    }
+
+    pub fn size_of_image(&self) -> u32
+    {
+        opt_field!(self, size_of_image)
+    }
+
+    pub fn size_of_headers(&self) -> u32
+    {
+        opt_field!(self, size_of_headers)
+    }
+
+    pub fn check_sum(&self) -> u32
+    {
+        opt_field!(self, check_sum)
+    }
+
+    pub fn subsystem(&self) -> Subsystem
+    {
+        Subsystem::from(opt_field!(self, subsystem))
+    }
+
+    pub fn dll_characteristics(&self) -> DllCharacteristics
+    {
+        DllCharacteristics(opt_field!(self, dll_characteristics))
+    }
+
+    pub fn size_of_stack_reserve(&self) -> u64
+    {
+        match self
+        {
+            OptionalHeader::Pe32(h) => h.size_of_stack_reserve as u64,
+            OptionalHeader::Pe64(h) => h.size_of_stack_reserve,
+        }
+    }
+
+    pub fn size_of_heap_reserve(&self) -> u64
+    {
+        match self
+        {
+            OptionalHeader::Pe32(h) => h.size_of_heap_reserve as u64,
+            OptionalHeader::Pe64(h) => h.size_of_heap_reserve,
+        }
+    }
+
+    pub fn number_of_rva_and_sizes(&self) -> usize
+    {
+        opt_field!(self, number_of_rva_and_sizes) as usize
+    }
+
+    // Entries actually present, NumberOfRvaAndSizes can be lower than 16
+    pub fn data_directories(&self) -> &[ImageDataDirectory]
+    {
+        let dirs = match self
+        {
+            OptionalHeader::Pe32(h) => &h.data_directory,
+            OptionalHeader::Pe64(h) => &h.data_directory,
+        };
+
+        &dirs[..self.number_of_rva_and_sizes().min(IMAGE_NUMBEROF_DIRECTORY_ENTRIES)]
+    }
+
+    // None when the entry is not present
+    pub fn data_directory(&self, entry: DataDirectory) -> Option<&ImageDataDirectory>
+    {
+        self.data_directories().get(entry as usize)
+    }
+}
+
+// =================================================== Machine / Subsystem
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Machine
+{
+    Unknown,
+    I386,
+    Amd64,
+    Arm,
+    ArmNt,
+    Arm64,
+    Ia64,
+    Other(u16),
+}
+
+impl From<u16> for Machine
+{
+    fn from(value: u16) -> Machine
+    {
+        match value
+        {
+            0x0000 => Machine::Unknown,
+            0x014c => Machine::I386,
+            0x8664 => Machine::Amd64,
+            0x01c0 => Machine::Arm,
+            0x01c4 => Machine::ArmNt,
+            0xaa64 => Machine::Arm64,
+            0x0200 => Machine::Ia64,
+            v => Machine::Other(v),
+        }
+    }
+}
+
+impl From<Machine> for u16
+{
+    fn from(machine: Machine) -> u16
+    {
+        match machine
+        {
+            Machine::Unknown => 0x0000,
+            Machine::I386 => 0x014c,
+            Machine::Amd64 => 0x8664,
+            Machine::Arm => 0x01c0,
+            Machine::ArmNt => 0x01c4,
+            Machine::Arm64 => 0xaa64,
+            Machine::Ia64 => 0x0200,
+            Machine::Other(v) => v,
+        }
+    }
+}
+
+impl Machine
+{
+    // Pointer width of the architecture, None for unknown machines
+    pub fn bitness(&self) -> Option<Bitness>
+    {
+        match self
+        {
+            Machine::I386 | Machine::Arm | Machine::ArmNt => Some(Bitness::Bit32),
+            Machine::Amd64 | Machine::Arm64 | Machine::Ia64 => Some(Bitness::Bit64),
+            Machine::Unknown | Machine::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Machine
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Machine::Other(v) => write!(f, "Machine({:#x})", v),
+            m => write!(f, "{:?}", m),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Subsystem
+{
+    Unknown,
+    Native,
+    WindowsGui,
+    WindowsCui,
+    Os2Cui,
+    PosixCui,
+    NativeWindows,
+    WindowsCeGui,
+    EfiApplication,
+    EfiBootServiceDriver,
+    EfiRuntimeDriver,
+    EfiRom,
+    Xbox,
+    WindowsBootApplication,
+    Other(u16),
+}
+
+impl From<u16> for Subsystem
+{
+    fn from(value: u16) -> Subsystem
+    {
+        match value
+        {
+            0 => Subsystem::Unknown,
+            1 => Subsystem::Native,
+            2 => Subsystem::WindowsGui,
+            3 => Subsystem::WindowsCui,
+            5 => Subsystem::Os2Cui,
+            7 => Subsystem::PosixCui,
+            8 => Subsystem::NativeWindows,
+            9 => Subsystem::WindowsCeGui,
+            10 => Subsystem::EfiApplication,
+            11 => Subsystem::EfiBootServiceDriver,
+            12 => Subsystem::EfiRuntimeDriver,
+            13 => Subsystem::EfiRom,
+            14 => Subsystem::Xbox,
+            16 => Subsystem::WindowsBootApplication,
+            v => Subsystem::Other(v),
+        }
+    }
+}
+
+impl fmt::Display for Subsystem
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Subsystem::Other(v) => write!(f, "Subsystem({})", v),
+            s => write!(f, "{:?}", s),
+        }
+    }
+}
+
+// =================================================== Flags
+
+// Bit flags over an integer, with the name of every known flag
+// Multi-bit fields are declared after the flags with the function naming their value: [NAME] = mask => describe;
+macro_rules! pe_flags
+{
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident($t:ty)
+        {
+            $( $flag:ident = $value:expr; )*
+            $( [$field:ident] = $mask:expr => $describe:path; )*
+        }
+    ) =>
+    {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct $name(pub $t);
+
+        impl $name
+        {
+            $( pub const $flag: $name = $name($value); )*
+
+            const NAMES: &'static [(&'static str, $t)] = &[ $( (stringify!($flag), $value), )* ];
+
+            // Bits holding a value rather than a flag
+            const FIELDS: $t = 0 $( | $mask )*;
+
+            pub fn bits(self) -> $t
+            {
+                self.0
+            }
+
+            pub fn contains(self, other: $name) -> bool
+            {
+                self.0 & other.0 == other.0
+            }
+
+            pub fn is_empty(self) -> bool
+            {
+                self.0 == 0
+            }
+
+            // Names of the known flags that are set
+            pub fn names(self) -> Vec<&'static str>
+            {
+                Self::NAMES.iter().filter(|(_, v)| self.0 & v != 0).map(|(n, _)| *n).collect()
+            }
+
+            // Bits set that have no name and are not part of a field
+            pub fn unknown_bits(self) -> $t
+            {
+                Self::NAMES.iter().fold(self.0 & !Self::FIELDS, |bits, (_, v)| bits & !v)
+            }
+        }
+
+        impl BitOr for $name
+        {
+            type Output = $name;
+
+            fn bitor(self, other: $name) -> $name
+            {
+                $name(self.0 | other.0)
+            }
+        }
+
+        impl BitAnd for $name
+        {
+            type Output = $name;
+
+            fn bitand(self, other: $name) -> $name
+            {
+                $name(self.0 & other.0)
+            }
+        }
+
+        // e.g. EXECUTABLE_IMAGE | DLL
+        impl fmt::Display for $name
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+            {
+                let mut parts: Vec<String> = self.names().iter().map(|n| n.to_string()).collect();
+
+                $(
+                    if self.0 & $mask != 0
+                    {
+                        parts.push($describe(self.0 & $mask));
+                    }
+                )*
+
+                if self.unknown_bits() != 0
+                {
+                    parts.push(format!("{:#x}", self.unknown_bits()));
+                }
+
+                if parts.is_empty()
+                {
+                    return write!(f, "0");
+                }
+
+                write!(f, "{}", parts.join(" | "))
+            }
+        }
+
+        impl fmt::Debug for $name
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+            {
+                write!(f, "{}({:#x}: {})", stringify!($name), self.0, self)
+            }
+        }
+    };
+}
+
+pe_flags!
+{
+    // IMAGE_FILE_HEADER.Characteristics
+    pub struct FileCharacteristics(u16)
+    {
+        RELOCS_STRIPPED = 0x0001;
+        EXECUTABLE_IMAGE = 0x0002;
+        LINE_NUMS_STRIPPED = 0x0004;
+        LOCAL_SYMS_STRIPPED = 0x0008;
+        AGGRESSIVE_WS_TRIM = 0x0010;
+        LARGE_ADDRESS_AWARE = 0x0020;
+        BYTES_REVERSED_LO = 0x0080;
+        MACHINE_32BIT = 0x0100;
+        DEBUG_STRIPPED = 0x0200;
+        REMOVABLE_RUN_FROM_SWAP = 0x0400;
+        NET_RUN_FROM_SWAP = 0x0800;
+        SYSTEM = 0x1000;
+        DLL = 0x2000;
+        UP_SYSTEM_ONLY = 0x4000;
+        BYTES_REVERSED_HI = 0x8000;
+    }
+}
+
+pe_flags!
+{
+    // IMAGE_OPTIONAL_HEADER.DllCharacteristics
+    pub struct DllCharacteristics(u16)
+    {
+        HIGH_ENTROPY_VA = 0x0020;
+        DYNAMIC_BASE = 0x0040;
+        FORCE_INTEGRITY = 0x0080;
+        NX_COMPAT = 0x0100;
+        NO_ISOLATION = 0x0200;
+        NO_SEH = 0x0400;
+        NO_BIND = 0x0800;
+        APPCONTAINER = 0x1000;
+        WDM_DRIVER = 0x2000;
+        GUARD_CF = 0x4000;
+        TERMINAL_SERVER_AWARE = 0x8000;
+    }
+}
+
+pe_flags!
+{
+    // IMAGE_SECTION_HEADER.Characteristics, the value of the IMAGE_SCN_ALIGN_* field is given by alignment()
+    pub struct SectionCharacteristics(u32)
+    {
+        CNT_CODE = 0x0000_0020;
+        CNT_INITIALIZED_DATA = 0x0000_0040;
+        CNT_UNINITIALIZED_DATA = 0x0000_0080;
+        LNK_INFO = 0x0000_0200;
+        LNK_REMOVE = 0x0000_0800;
+        LNK_COMDAT = 0x0000_1000;
+        GPREL = 0x0000_8000;
+        LNK_NRELOC_OVFL = 0x0100_0000;
+        MEM_DISCARDABLE = 0x0200_0000;
+        MEM_NOT_CACHED = 0x0400_0000;
+        MEM_NOT_PAGED = 0x0800_0000;
+        MEM_SHARED = 0x1000_0000;
+        MEM_EXECUTE = 0x2000_0000;
+        MEM_READ = 0x4000_0000;
+        MEM_WRITE = 0x8000_0000;
+        [ALIGN] = SectionCharacteristics::ALIGN_MASK => SectionCharacteristics::align_name;
+    }
+}
+
+impl SectionCharacteristics
+{
+    const ALIGN_MASK: u32 = 0x00f0_0000;
+
+    // Alignment of the section data in object files, from IMAGE_SCN_ALIGN_1BYTES to IMAGE_SCN_ALIGN_8192BYTES
+    // None when unset, or for the undefined value 0xF
+    pub fn alignment(self) -> Option<usize>
+    {
+        match (self.0 & Self::ALIGN_MASK) >> 20
+        {
+            n @ 1..=14 => Some(1 << (n - 1)),
+            _ => None,
+        }
+    }
+
+    // e.g. ALIGN_16BYTES
+    fn align_name(bits: u32) -> String
+    {
+        match SectionCharacteristics(bits).alignment()
+        {
+            Some(align) => format!("ALIGN_{}BYTES", align),
+            None => format!("ALIGN_INVALID({:#x})", bits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn flags_are_named()
+    {
+        let c = FileCharacteristics::EXECUTABLE_IMAGE | FileCharacteristics::DLL;
+
+        assert!(c.contains(FileCharacteristics::DLL));
+        assert!(!c.contains(FileCharacteristics::SYSTEM));
+        assert_eq!(c.names(), ["EXECUTABLE_IMAGE", "DLL"]);
+        assert_eq!(c.to_string(), "EXECUTABLE_IMAGE | DLL");
+
+        // 0x0040 is reserved
+        let c = FileCharacteristics(0x2042);
+        assert_eq!(c.unknown_bits(), 0x40);
+        assert_eq!(c.to_string(), "EXECUTABLE_IMAGE | DLL | 0x40");
+        assert_eq!(DllCharacteristics(0).to_string(), "0");
+    }
+
+    #[test]
+    fn section_alignment_is_a_field()
+    {
+        let c = SectionCharacteristics(0x6050_0020);
+
+        assert_eq!(c.alignment(), Some(16));
+        assert_eq!(c.unknown_bits(), 0);
+        assert_eq!(c.to_string(), "CNT_CODE | MEM_EXECUTE | MEM_READ | ALIGN_16BYTES");
+
+        assert_eq!(SectionCharacteristics(0x0010_0000).alignment(), Some(1));
+        assert_eq!(SectionCharacteristics(0x00e0_0000).alignment(), Some(8192));
+        assert_eq!(SectionCharacteristics(0x4000_0040).alignment(), None);
+        assert_eq!(SectionCharacteristics(0x4000_0040).to_string(), "CNT_INITIALIZED_DATA | MEM_READ");
+    }
+
+    #[test]
+    fn undefined_section_alignment()
+    {
+        let c = SectionCharacteristics(0x40f0_0000);
+
+        assert_eq!(c.alignment(), None);
+        assert_eq!(c.unknown_bits(), 0);
+        assert_eq!(c.to_string(), "MEM_READ | ALIGN_INVALID(0xf00000)");
+
+        // Reserved bits outside of the field are still reported
+        assert_eq!(SectionCharacteristics(0x0050_0001).unknown_bits(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+// What an import thunk refers to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImportThunk
+{
+    ByName { hint: u16, name: String },     // IMAGE_IMPORT_BY_NAME
+    ByOrdinal(u16),
+    Unknown,    // No lookup table and the IAT was already filled by the loader or bound
+}
+
+// A function imported from a DLL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedFunction
+{
+    pub thunk: ImportThunk,
+    pub iat_rva: usize,             // RVA of the IAT slot the loader patches
+    pub resolved: Option<usize>,    // Current content of the IAT slot, for mapped images and bound IATs
+}
+
+// An IMAGE_IMPORT_DESCRIPTOR and its thunks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImportedDll
+{
+    pub name: String,
+    pub time_date_stamp: u32,
+    pub forwarder_chain: u32,
+    pub original_first_thunk: u32,
+    pub first_thunk: u32,
+    pub functions: Vec<ImportedFunction>,
+}
+
+// The import directory of an image
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Imports
+{
+    pub dlls: Vec<ImportedDll>,
+    pub issues: Vec<String>,    // Descriptors and thunks that could not be parsed, empty for a sane image
+}
+
+impl ImportThunk
+{
+    pub fn name(&self) -> Option<&str>
+    {
+        match self
+        {
+            ImportThunk::ByName { name, .. } => Some(name),
+            ImportThunk::ByOrdinal(_) | ImportThunk::Unknown => None,
+        }
+    }
+
+    pub fn ordinal(&self) -> Option<u16>
+    {
+        match self
+        {
+            ImportThunk::ByName { .. } | ImportThunk::Unknown => None,
+            ImportThunk::ByOrdinal(ord) => Some(*ord),
+        }
+    }
+}
+
+impl ImportedDll
+{
+    // A bound IAT already holds the addresses, the timestamp is then -1 (new style) or the one of the DLL
+    pub fn is_bound(&self) -> bool
+    {
+        self.time_date_stamp != 0
+    }
+
+    pub fn function(&self, name: &str) -> Option<&ImportedFunction>
+    {
+        self.functions.iter().find(|f| f.thunk.name() == Some(name))
+    }
+}
+
+impl Imports
+{
+    // DLL names are case insensitive on Windows
+    pub fn dll(&self, name: &str) -> Option<&ImportedDll>
+    {
+        self.dlls.iter().find(|d| d.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for ImportThunk
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            ImportThunk::ByName { hint, name } => write!(f, "{} (hint {})", name, hint),
+            ImportThunk::ByOrdinal(ord) => write!(f, "#{}", ord),
+            ImportThunk::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+impl fmt::Display for ImportedFunction
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:#010x} {}", self.iat_rva, self.thunk)?;
+
+        if let Some(addr) = self.resolved
+        {
+            write!(f, " -> {:#x}", addr)?;
+        }
+
+        Ok(())
+    }
+}
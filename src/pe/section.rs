@@ -0,0 +1,106 @@
+use super::headers::{ImageSectionHeader, SectionCharacteristics};
+use std::fmt;
+
+// Raw data pointers are rounded down to this boundary by the loader, whatever FileAlignment says
+const MIN_FILE_ALIGNMENT: usize = 0x200;
+
+// An entry of the section table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section
+{
+    pub name: String,
+    pub virtual_address: usize,
+    pub virtual_size: usize,
+    pub raw_ptr: usize,     // PointerToRawData
+    pub raw_size: usize,    // SizeOfRawData
+    pub characteristics: SectionCharacteristics,
+}
+
+// Saturates, sizes come from the headers and may be anything
+fn align_up(value: usize, alignment: usize) -> usize
+{
+    if alignment <= 1 { value } else { value.checked_next_multiple_of(alignment).unwrap_or(usize::MAX) }
+}
+
+fn align_down(value: usize, alignment: usize) -> usize
+{
+    if alignment <= 1 { value } else { value - value % alignment }
+}
+
+impl Section
+{
+    pub fn from_header(header: &ImageSectionHeader) -> Section
+    {
+        // The name is null padded, and not null terminated when 8 characters long
+        let name = header.name.split(|&b| b == 0).next().unwrap_or(&[]);
+
+        Section { name: String::from_utf8_lossy(name).to_string(),
+                  virtual_address: header.virtual_address as usize,
+                  virtual_size: header.virtual_size as usize,
+                  raw_ptr: header.pointer_to_raw_data as usize,
+                  raw_size: header.size_of_raw_data as usize,
+                  characteristics: SectionCharacteristics(header.characteristics) }
+    }
+
+    // Size of the section once mapped, before alignment
+    // A VirtualSize of 0 (some linkers) means SizeOfRawData
+    pub fn virtual_extent(&self) -> usize
+    {
+        if self.virtual_size == 0 { self.raw_size } else { self.virtual_size }
+    }
+
+    // Size of the section once mapped, rounded up to SectionAlignment
+    pub fn mapped_extent(&self, section_alignment: usize) -> usize
+    {
+        align_up(self.virtual_extent(), section_alignment)
+    }
+
+    // Where the loader actually starts reading the raw data
+    pub fn raw_start(&self, file_alignment: usize) -> usize
+    {
+        if file_alignment < MIN_FILE_ALIGNMENT { self.raw_ptr } else { align_down(self.raw_ptr, MIN_FILE_ALIGNMENT) }
+    }
+
+    // Number of bytes of raw data the loader maps
+    // SizeOfRawData is rounded up to FileAlignment, and never extends past the mapped size of the section
+    pub fn raw_extent(&self, file_alignment: usize, section_alignment: usize) -> usize
+    {
+        if self.raw_ptr == 0
+        {
+            return 0;
+        }
+
+        let raw = align_up(self.raw_size, file_alignment);
+
+        if self.virtual_size == 0 { raw } else { raw.min(self.mapped_extent(section_alignment)) }
+    }
+
+    pub fn contains_rva(&self, rva: usize, section_alignment: usize) -> bool
+    {
+        rva >= self.virtual_address && rva - self.virtual_address < self.mapped_extent(section_alignment)
+    }
+
+    pub fn is_code(&self) -> bool
+    {
+        self.characteristics.contains(SectionCharacteristics::CNT_CODE)
+    }
+
+    pub fn is_executable(&self) -> bool
+    {
+        self.characteristics.contains(SectionCharacteristics::MEM_EXECUTE)
+    }
+
+    pub fn is_writable(&self) -> bool
+    {
+        self.characteristics.contains(SectionCharacteristics::MEM_WRITE)
+    }
+}
+
+impl fmt::Display for Section
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{:<8} VA: {:#010x} VSize: {:#x} Raw: {:#010x} RawSize: {:#x} [{}]",
+               self.name, self.virtual_address, self.virtual_size, self.raw_ptr, self.raw_size, self.characteristics)
+    }
+}
@@ -1,17 +1,68 @@
 use crate::err::*;
+use crate::memory::{MemorySource, LiveMemory, Bitness, ListEntry, ListEntryIter, RemotePtr, UnicodeString, WideString};
 use std::fmt;
 use std::arch::asm;
 
-pub struct Peb
+nt_struct!
+{
+    // PEB, up to ProcessParameters
+    pub struct ProcessEnvironmentBlock : ProcessEnvironmentBlockOffsets
+    {
+        inherited_address_space:        u8      = 0x00, 0x00;
+        read_image_file_exec_options:   u8      = 0x01, 0x01;
+        being_debugged:                 u8      = 0x02, 0x02;
+        bit_field:                      u8      = 0x03, 0x03;
+        mutant:                         usize   = 0x04, 0x08;
+        image_base_address:             usize   = 0x08, 0x10;
+        ldr:                            usize   = 0x0c, 0x18;
+        process_parameters:             usize   = 0x10, 0x20;
+    }
+}
+
+nt_struct!
+{
+    // PEB_LDR_DATA
+    pub struct PebLdrData : PebLdrDataOffsets
+    {
+        length:                                 u32         = 0x00, 0x00;
+        initialized:                            u8          = 0x04, 0x04;
+        ss_handle:                              usize       = 0x08, 0x08;
+        in_load_order_module_list:              ListEntry   = 0x0c, 0x10;
+        in_memory_order_module_list:            ListEntry   = 0x14, 0x20;
+        in_initialization_order_module_list:    ListEntry   = 0x1c, 0x30;
+    }
+}
+
+nt_struct!
+{
+    // LDR_DATA_TABLE_ENTRY, up to BaseDllName
+    pub struct LdrDataTableEntry : LdrDataTableEntryOffsets
+    {
+        in_load_order_links:            ListEntry       = 0x00, 0x00;
+        in_memory_order_links:          ListEntry       = 0x08, 0x10;
+        in_initialization_order_links:  ListEntry       = 0x10, 0x20;
+        dll_base:                       usize           = 0x18, 0x30;
+        entry_point:                    usize           = 0x1c, 0x38;
+        size_of_image:                  u32             = 0x20, 0x40;
+        full_dll_name:                  UnicodeString   = 0x24, 0x48;
+        base_dll_name:                  UnicodeString   = 0x2c, 0x58;
+    }
+}
+
+pub struct Peb<M: MemorySource = LiveMemory>
 {
     pub base_addr: usize,
+    mem: M,
+    bitness: Bitness,   // Layout of the PEB and of the loader structures
 }
 
 // Simple implementation
 // Only ldr data is accessible right now
-impl Peb
+impl Peb<LiveMemory>
 {
-    pub fn new() -> Peb
+    /// # Safety
+    /// Only valid in a 64-bit Windows process, gs:[0x60] is the PEB there
+    pub unsafe fn new() -> Peb
     {
 
         let peb_addr: usize;
@@ -21,127 +72,207 @@ impl Peb
                  "mov r8, gs:[rax + 0x60]",    // load peb addr into rbx
                 out("r8") peb_addr);
         }
-        Peb { base_addr: peb_addr }
+        Peb { base_addr: peb_addr, mem: LiveMemory::new(), bitness: Bitness::host() }
+    }
+}
+
+impl<M: MemorySource + Clone> Peb<M>
+{
+    // PEB located at base_addr inside the given memory source
+    pub fn with_source(mem: M, base_addr: usize) -> Peb<M>
+    {
+        Peb::with_bitness(mem, base_addr, Bitness::host())
     }
 
-    pub fn get_ldr(&self) -> Ldr
+    // PEB of a target whose pointer width differs from the host, e.g. the 32-bit PEB of a WoW64 process
+    pub fn with_bitness(mem: M, base_addr: usize, bitness: Bitness) -> Peb<M>
     {
-        unsafe 
-        {
-            let base_addr: usize = *((self.base_addr + 0x18) as *const usize) as usize;
-            Ldr::new(base_addr)
-        }
+        Peb { base_addr, mem, bitness }
+    }
+
+    pub fn bitness(&self) -> Bitness
+    {
+        self.bitness
+    }
+
+    pub fn ptr(&self) -> RemotePtr<'_, ProcessEnvironmentBlock, M>
+    {
+        RemotePtr::with_bitness(&self.mem, self.base_addr, self.bitness)
+    }
+
+    pub fn read(&self) -> Result<ProcessEnvironmentBlock, PEErr>
+    {
+        self.ptr().read()
+    }
+
+    // PEB->Ldr
+    pub fn get_ldr(&self) -> Result<Ldr<M>, PEErr>
+    {
+        let ldr = self.ptr()
+                      .field::<usize>(|o| o.ldr)
+                      .deref_ptr::<PebLdrData>()?
+                      .non_null()
+                      .ok_or_else(|| PEErr::failure("PEB Ldr is null"))?;
+
+        Ldr::with_bitness(self.mem.clone(), ldr.addr(), self.bitness)
     }
 }
 
-pub struct Ldr
+pub struct Ldr<M: MemorySource = LiveMemory>
 {
-    pub in_load_order_module_list: LdrModule,
-    pub in_memory_order_module_list: LdrModule,
-    pub in_initialization_order_module_list: LdrModule,
+    pub in_load_order_module_list: LdrModule<M>,
+    pub in_memory_order_module_list: LdrModule<M>,
+    pub in_initialization_order_module_list: LdrModule<M>,
 }
 
-impl Ldr
+impl Ldr<LiveMemory>
 {
-    pub fn new(base_addr: usize) -> Ldr
+    /// # Safety
+    /// `base_addr` must point to the PEB_LDR_DATA of the current process
+    pub unsafe fn new(base_addr: usize) -> Result<Ldr, PEErr>
     {
-        Ldr {in_load_order_module_list: LdrModule::new(base_addr + 0x10, 0),
-             in_memory_order_module_list: LdrModule::new(base_addr + 0x20, 0x10),
-             in_initialization_order_module_list: LdrModule::new(base_addr + 0x30, 0x20) }
+        Ldr::with_source(LiveMemory::new(), base_addr)
     }
 }
 
+impl<M: MemorySource + Clone> Ldr<M>
+{
+    pub fn with_source(mem: M, base_addr: usize) -> Result<Ldr<M>, PEErr>
+    {
+        Ldr::with_bitness(mem, base_addr, Bitness::host())
+    }
 
-pub struct LdrModule
+    pub fn with_bitness(mem: M, base_addr: usize, bitness: Bitness) -> Result<Ldr<M>, PEErr>
+    {
+        // List heads inside PEB_LDR_DATA, and the matching links inside LDR_DATA_TABLE_ENTRY
+        let ldr = RemotePtr::<PebLdrData, M>::with_bitness(&mem, base_addr, bitness);
+        let load = ldr.field::<ListEntry>(|o| o.in_load_order_module_list).addr();
+        let memory = ldr.field::<ListEntry>(|o| o.in_memory_order_module_list).addr();
+        let init = ldr.field::<ListEntry>(|o| o.in_initialization_order_module_list).addr();
+
+        let entry = LdrDataTableEntry::offsets(bitness);
+
+        Ok(Ldr {in_load_order_module_list: LdrModule::with_bitness(mem.clone(), load, entry.in_load_order_links, bitness)?,
+                in_memory_order_module_list: LdrModule::with_bitness(mem.clone(), memory, entry.in_memory_order_links, bitness)?,
+                in_initialization_order_module_list: LdrModule::with_bitness(mem, init, entry.in_initialization_order_links, bitness)? })
+    }
+}
+
+
+pub struct LdrModule<M: MemorySource = LiveMemory>
 {
-    list_header: usize,      // Address of the list header
+    mem:         M,           // Memory the list lives in
+    list_header: usize,       // Address of the list header
     modules:     Vec<Module>, // List of addresses of each entry
-    base_addr:   usize,      // Address the current entry
-    flink:       usize,      // Current address pointed by the flink ptr
-    blink:       usize,      // Current address pointed by the blink ptr
-    offset:      usize,      // Offset of the ListEntry position inside the containing structure
+    names:       Vec<WideString>, // Raw BaseDllName of each entry
+    entries:     Vec<usize>,  // Address of each LDR_DATA_TABLE_ENTRY
+    index:       usize,       // Current entry
+    offset:      usize,       // Offset of the ListEntry position inside the containing structure
+    bitness:     Bitness,     // Layout of the entries
 }
 
-// TODO: Proper error handling
-// Currently only returning Oks
-impl LdrModule
+impl LdrModule<LiveMemory>
 {
-    pub fn new(header_addr: usize, offset: usize) -> LdrModule
+    /// # Safety
+    /// `header_addr` must point to a loader list head of the current process
+    pub unsafe fn new(header_addr: usize, offset: usize) -> Result<LdrModule, PEErr>
     {
-        let mut le = LdrModule { list_header: header_addr, 
-                                 modules: Vec::new(),
-                                 base_addr: 0, 
-                                 flink: 0, 
-                                 blink: 0,
-                                 offset};
-
-        le.init();
-        le
+        LdrModule::with_source(LiveMemory::new(), header_addr, offset)
     }
+}
 
-    fn init(&mut self)
+impl<M: MemorySource> LdrModule<M>
+{
+    pub fn with_source(mem: M, header_addr: usize, offset: usize) -> Result<LdrModule<M>, PEErr>
     {
-        self.reset();
+        LdrModule::with_bitness(mem, header_addr, offset, Bitness::host())
+    }
 
-        self.modules.push(self.module().unwrap());
+    pub fn with_bitness(mem: M, header_addr: usize, offset: usize, bitness: Bitness) -> Result<LdrModule<M>, PEErr>
+    {
+        let mut le = LdrModule { mem,
+                                 list_header: header_addr,
+                                 modules: Vec::new(),
+                                 names: Vec::new(),
+                                 entries: Vec::new(),
+                                 index: 0,
+                                 offset,
+                                 bitness };
+
+        le.init()?;
+        Ok(le)
+    }
 
-        while let Ok(_) = self.next()
+    fn init(&mut self) -> Result<(), PEErr>
+    {
+        for entry in ListEntryIter::new(&self.mem, self.list_header, self.offset).bitness(self.bitness)
         {
-            self.modules.push(self.module().unwrap());
+            let addr = entry?;
+            let entry = LdrDataTableEntry::read(&self.mem, addr, self.bitness)?;
+
+            self.names.push(entry.base_dll_name.read_wide(&self.mem)?);
+            self.modules.push(LdrModule::to_module(&self.mem, &entry)?);
+            self.entries.push(addr);
         }
 
-        self.reset();
+        self.reset()
     }
 
-    pub fn reset(&mut self)
+    pub fn reset(&mut self) -> Result<(), PEErr>
     {
-        unsafe
-        {
-            self.base_addr = *(self.list_header as *const usize) as usize;
-            self.flink = *(self.base_addr as *const usize) as usize;
-            self.blink = *((self.base_addr + 0x8) as *const usize) as usize;
-        }
+        self.index = 0;
+        Ok(())
     }
-        
+
+    // Move to the next entry, fails once the end of the list is reached
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<(), PEErr>
     {
-        if self.flink == self.list_header
+        if self.index + 1 >= self.entries.len()
         {
-            return Err(PEErr {
-                        status: ErrState::Failure,
-                        message: String::from("Error, cannot reach the next 
-                                                list entry: end of list reached.")} );
+            return Err(PEErr::failure("Error, cannot reach the next list entry: end of list reached."));
         }
 
-        self.base_addr = self.flink;
-        unsafe
-        {
-            self.flink = *(self.base_addr as *const usize) as usize;
-            self.blink = *((self.base_addr + 0x8) as *const usize) as usize;
-        }
+        self.index += 1;
 
         Ok(())
     }
-    
+
+    pub fn len(&self) -> usize
+    {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.modules.is_empty()
+    }
+
     pub fn module(&self) -> Result<Module, PEErr>
     {
-        LdrModule::get_module(self.base_addr, self.offset)
+        LdrModule::to_module(&self.mem, &self.entry()?)
     }
 
+    // Module names are compared case-insensitively, like the loader does
     pub fn find_module(&self, mod_name: &str) -> Result<Module, PEErr>
     {
-        for m in &self.modules
+        self.find_module_wide(&WideString::from(mod_name))
+    }
+
+    pub fn find_module_wide(&self, mod_name: &WideString) -> Result<Module, PEErr>
+    {
+        for (m, name) in self.modules.iter().zip(&self.names)
         {
-            if m.name == mod_name
+            if name.eq_ignore_case(mod_name)
             {
                 return Ok(m.clone())
             }
         }
 
-        Err( PEErr 
-             { 
-                status: ErrState::Failure, 
-                message: String::from("nope") 
+        Err( PEErr
+             {
+                status: ErrState::Failure,
+                message: String::from("nope")
              } )
 
         /*
@@ -151,97 +282,72 @@ impl LdrModule
             None => return Err( PEErr { status: ErrState::Failure, message: String::from("nope") } ),
         }
         */
-        
-        //self.modules.iter().filter(|&m| m.name == mod_name).collect::<Module>() 
+
+        //self.modules.iter().filter(|&m| m.name == mod_name).collect::<Module>()
     }
 
-    fn get_module(addr: usize, offset: usize) -> Result<Module, PEErr>
+    fn to_module(mem: &M, entry: &LdrDataTableEntry) -> Result<Module, PEErr>
     {
-        Ok( Module 
+        Ok( Module
             {
-                name:           LdrModule::get_module_name(addr, offset).unwrap(),
-                full_name:      LdrModule::get_module_full_name(addr, offset).unwrap(),
-                dll_base:       LdrModule::get_module_dll_base(addr, offset),
-                entry_point:    LdrModule::get_module_entry_point(addr, offset),
-                size_of_image:  LdrModule::get_module_size_of_image(addr, offset),
+                name:           entry.base_dll_name.read_string(mem)?,
+                full_name:      entry.full_dll_name.read_string(mem)?,
+                dll_base:       entry.dll_base,
+                entry_point:    entry.entry_point,
+                size_of_image:  entry.size_of_image as usize,
             })
     }
 
-    pub fn get_name(&self) -> Result<String, PEErr>
-    {
-        LdrModule::get_module_name(self.base_addr, self.offset)
-    }
-
-    pub fn get_full_name(&self) -> Result<String, PEErr>
-    {
-        LdrModule::get_module_full_name(self.base_addr, self.offset)
-    }
-
-    pub fn get_dll_base(&self) -> usize
-    {
-        LdrModule::get_module_dll_base(self.base_addr, self.offset)
-    }
-
-    pub fn get_entry_point(&self) -> usize
+    // Address of the current LDR_DATA_TABLE_ENTRY
+    fn current(&self) -> Result<usize, PEErr>
     {
-        LdrModule::get_module_entry_point(self.base_addr, self.offset)
+        self.entries.get(self.index)
+                    .copied()
+                    .ok_or_else(|| PEErr::failure(format!("Module list at {:#x} is empty", self.list_header)))
     }
 
-    pub fn get_size_of_image(&self) -> usize
+    // LDR_DATA_TABLE_ENTRY of the current entry
+    pub fn entry(&self) -> Result<LdrDataTableEntry, PEErr>
     {
-        LdrModule::get_module_size_of_image(self.base_addr, self.offset)
+        LdrDataTableEntry::read(&self.mem, self.current()?, self.bitness)
     }
 
-    fn get_module_name(addr: usize, offset: usize) -> Result<String, PEErr>
+    // LIST_ENTRY of the current entry, inside this list
+    pub fn links(&self) -> Result<ListEntry, PEErr>
     {
-        let name_addr: usize = LdrModule::compute_addr(addr, offset, 0x60);
-        
-        LdrModule::get_u16_string_at(name_addr)
+        ListEntry::read(&self.mem, self.current()? + self.offset, self.bitness)
     }
 
-    fn get_module_full_name(addr: usize, offset: usize) -> Result<String, PEErr>
-    {
-        let name_addr: usize = LdrModule::compute_addr(addr, offset, 0x50);
-        
-        LdrModule::get_u16_string_at(name_addr) 
-    }
-
-    fn get_u16_string_at(addr: usize) -> Result<String, PEErr>
+    pub fn get_name(&self) -> Result<String, PEErr>
     {
-        let bytes = read_null!(addr, u16);
-        let string = crate::memory::utf16_to_str(&bytes.0[..]);
-
-        Ok(string)
+        self.entry()?.base_dll_name.read_string(&self.mem)
     }
 
-    fn get_module_dll_base(addr: usize, offset: usize) -> usize
+    pub fn get_full_name(&self) -> Result<String, PEErr>
     {
-        LdrModule::compute_addr(addr, offset, 0x30)
+        self.entry()?.full_dll_name.read_string(&self.mem)
     }
 
-    fn get_module_entry_point(addr: usize, offset: usize) -> usize
+    pub fn get_dll_base(&self) -> Result<usize, PEErr>
     {
-        LdrModule::compute_addr(addr, offset, 0x38)
+        Ok(self.entry()?.dll_base)
     }
 
-    fn get_module_size_of_image(addr: usize, offset: usize) -> usize
+    pub fn get_entry_point(&self) -> Result<usize, PEErr>
     {
-        LdrModule::compute_addr(addr, offset, 0x40)
+        Ok(self.entry()?.entry_point)
     }
 
-    fn compute_addr(addr: usize, ldr_offset: usize, data_offset: usize) -> usize
+    pub fn get_size_of_image(&self) -> Result<usize, PEErr>
     {
-        unsafe
-        {
-            *((addr + data_offset - ldr_offset) as *const usize)
-        }
+        Ok(self.entry()?.size_of_image as usize)
     }
 }
 
-impl<'a> IntoIterator for &'a LdrModule
+impl<'a, M: MemorySource> IntoIterator for &'a LdrModule<M>
 {
     type Item = Module;
-    type IntoIter = LdrModuleIterator<'a>;
+    type IntoIter = LdrModuleIterator<'a, M>;
 
     fn into_iter(self) -> Self::IntoIter
     {
@@ -253,13 +359,13 @@ impl<'a> IntoIterator for &'a LdrModule
     }
 }
 
-pub struct LdrModuleIterator<'a>
+pub struct LdrModuleIterator<'a, M: MemorySource = LiveMemory>
 {
-    ldr_module: &'a LdrModule,
+    ldr_module: &'a LdrModule<M>,
     index: usize,
 }
 
-impl<'a> Iterator for LdrModuleIterator<'a>
+impl<M: MemorySource> Iterator for LdrModuleIterator<'_, M>
 {
     type Item = Module;
 
@@ -285,28 +391,30 @@ impl<'a> Iterator for LdrModuleIterator<'a>
     }
 }
 
-impl fmt::Display for LdrModule 
+impl<M: MemorySource> fmt::Display for LdrModule<M>
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result 
     {
+        let links = self.links().unwrap_or_default();
+
         write!(f,"[- {:#x} | {} -]\n\
                   flink: {:#x}\n\
                   blink: {:#x}",
-                  self.base_addr,
-                  self.get_name().unwrap(),
-                  self.flink,
-                  self.blink)
+                  self.current().map(|e| e + self.offset).unwrap_or_default(),
+                  self.get_name().unwrap_or_default(),
+                  links.flink,
+                  links.blink)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Module
 {
-    pub name: String,           // 0x60
-    pub full_name: String,      // 0x50
-    pub dll_base: usize,        // 0x30
-    pub entry_point: usize,     // 0x38
-    pub size_of_image: usize,   // 0x40
+    pub name: String,           // BaseDllName
+    pub full_name: String,      // FullDllName
+    pub dll_base: usize,        // DllBase
+    pub entry_point: usize,     // EntryPoint
+    pub size_of_image: usize,   // SizeOfImage
 }
 
 impl fmt::Display for Module
@@ -327,3 +435,130 @@ impl fmt::Display for Module
 }
 
 
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    const PEB_ADDR: usize = 0x7ff0_0000;
+
+    fn wptr(b: &mut [u8], off: usize, v: usize, bitness: Bitness)
+    {
+        match bitness
+        {
+            Bitness::Bit32 => b[off..off + 4].copy_from_slice(&(v as u32).to_le_bytes()),
+            Bitness::Bit64 => b[off..off + 8].copy_from_slice(&(v as u64).to_le_bytes()),
+        }
+    }
+
+    // PEB, PEB_LDR_DATA and two LDR_DATA_TABLE_ENTRY, initialization order reversed
+    fn process(bitness: Bitness) -> BufferMemory
+    {
+        let b64 = bitness == Bitness::Bit64;
+        let ps = bitness.ptr_size();
+        let at = |off: usize| PEB_ADDR + off;
+        let mut b = vec![0u8; 0x1000];
+
+        let ldr = 0x100;
+        wptr(&mut b, if b64 { 0x18 } else { 0x0c }, at(ldr), bitness);
+
+        let heads = if b64 { [0x10, 0x20, 0x30] } else { [0x0c, 0x14, 0x1c] };
+        let entries = [0x200, 0x400];
+        let modules = [("ntdll.dll", "C:\\Windows\\System32\\ntdll.dll", 0x7ffe_0000),
+                       ("KERNEL32.DLL", "C:\\Windows\\System32\\KERNEL32.DLL", 0x7ffd_0000)];
+
+        for (list, head) in heads.iter().enumerate()
+        {
+            let head = ldr + head;
+            let links = list * 2 * ps;
+            let order = if list == 2 { [entries[1], entries[0]] } else { entries };
+            let (first, second) = (order[0] + links, order[1] + links);
+
+            wptr(&mut b, head, at(first), bitness);
+            wptr(&mut b, head + ps, at(second), bitness);
+            wptr(&mut b, first, at(second), bitness);
+            wptr(&mut b, first + ps, at(head), bitness);
+            wptr(&mut b, second, at(head), bitness);
+            wptr(&mut b, second + ps, at(first), bitness);
+        }
+
+        let mut strings = 0x800;
+        for (e, (name, full, base)) in entries.iter().zip(modules)
+        {
+            let fields = if b64 { 0x30 } else { 0x18 };
+            wptr(&mut b, e + fields, base, bitness);
+            wptr(&mut b, e + fields + ps, base + 0x1000, bitness);
+            b[e + fields + 2 * ps..e + fields + 2 * ps + 4].copy_from_slice(&0x20000u32.to_le_bytes());
+
+            // FullDllName then BaseDllName, UNICODE_STRING is 8 or 16 bytes
+            let ustr = if b64 { 0x48 } else { 0x24 };
+            for (i, s) in [full, name].iter().enumerate()
+            {
+                let field = e + ustr + i * 2 * ps;
+                let wide: Vec<u8> = s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+                b[field..field + 2].copy_from_slice(&(wide.len() as u16).to_le_bytes());
+                b[field + 2..field + 4].copy_from_slice(&(wide.len() as u16 + 2).to_le_bytes());
+                wptr(&mut b, field + ps, at(strings), bitness);
+                b[strings..strings + wide.len()].copy_from_slice(&wide);
+                strings += wide.len() + 0x10;
+            }
+        }
+
+        BufferMemory::new(PEB_ADDR, b)
+    }
+
+    fn names<M: MemorySource>(list: &LdrModule<M>) -> Vec<String>
+    {
+        list.into_iter().map(|m| m.name).collect()
+    }
+
+    #[test]
+    fn walks_the_loader_lists()
+    {
+        for bitness in [Bitness::Bit32, Bitness::Bit64]
+        {
+            let peb = Peb::with_bitness(process(bitness), PEB_ADDR, bitness);
+            assert_eq!(peb.read().unwrap().ldr, PEB_ADDR + 0x100);
+
+            let ldr = peb.get_ldr().unwrap();
+            assert_eq!(names(&ldr.in_load_order_module_list), ["ntdll.dll", "KERNEL32.DLL"]);
+            assert_eq!(names(&ldr.in_memory_order_module_list), ["ntdll.dll", "KERNEL32.DLL"]);
+            assert_eq!(names(&ldr.in_initialization_order_module_list), ["KERNEL32.DLL", "ntdll.dll"]);
+
+            let k32 = ldr.in_load_order_module_list.find_module("kernel32.dll").unwrap();
+            assert_eq!(k32.full_name, "C:\\Windows\\System32\\KERNEL32.DLL");
+            assert_eq!(k32.dll_base, 0x7ffd_0000);
+            assert_eq!(k32.entry_point, 0x7ffd_1000);
+            assert_eq!(k32.size_of_image, 0x20000);
+            assert!(ldr.in_load_order_module_list.find_module("user32.dll").is_err());
+        }
+    }
+
+    #[test]
+    fn next_fails_at_the_end_of_the_list()
+    {
+        for bitness in [Bitness::Bit32, Bitness::Bit64]
+        {
+            let ldr = Peb::with_bitness(process(bitness), PEB_ADDR, bitness).get_ldr().unwrap();
+            let mut m = ldr.in_initialization_order_module_list;
+
+            assert_eq!(m.get_name().unwrap(), "KERNEL32.DLL");
+            assert!(m.next().is_ok());
+            assert_eq!(m.get_dll_base().unwrap(), 0x7ffe_0000);
+            // Last entry, its flink goes back to the list head
+            let head = PEB_ADDR + 0x100 + if bitness == Bitness::Bit64 { 0x30 } else { 0x1c };
+            assert_eq!(m.links().unwrap().flink, head);
+            assert!(m.next().is_err());
+
+            // The loop of the original API terminates
+            m.reset().unwrap();
+            let mut count = 1;
+            while m.next().is_ok()
+            {
+                count += 1;
+            }
+            assert_eq!(count, 2);
+        }
+    }
+}
@@ -0,0 +1,728 @@
+use nt_utils::memory::{BufferMemory, MemorySourceMut};
+use nt_utils::pe::{PEImage, PEName, Layout, ImportThunk, DataDirectory};
+use nt_utils::memory::Bitness;
+
+// =================================================== Synthetic image
+//
+// Headers up to 0x400, then three sections:
+//  .text   VA 0x1000 VSize 0x200 Raw 0x400 RawSize 0x200  syscall stubs of NtAlpha / NtBeta / NtGamma
+//  .rdata  VA 0x2000 VSize 0x300 Raw 0x600 RawSize 0x400  export directory of test.dll
+//  .idata  VA 0x3000 VSize 0x280 Raw 0xa00 RawSize 0x200  imports of KERNEL32.dll (by name and by ordinal)
+// SectionAlignment 0x1000, FileAlignment 0x200, SizeOfImage 0x4000
+
+const BASE32: usize = 0x1000_0000;
+const BASE64: usize = 0x1_8000_0000;
+const MAPPED_AT: usize = 0x40_0000;
+
+// Name, VirtualAddress, VirtualSize, PointerToRawData, SizeOfRawData, Characteristics
+type SectionHeader = (&'static [u8], u32, u32, u32, u32, u32);
+
+const SECTIONS: [SectionHeader; 3] =
+[
+    (b".text",  0x1000, 0x200, 0x400, 0x200, 0x6000_0020),
+    (b".rdata", 0x2000, 0x300, 0x600, 0x400, 0x4000_0040),
+    (b".idata", 0x3000, 0x280, 0xa00, 0x200, 0xc000_0040),
+];
+
+const SYSCALLS: [(&str, u32); 3] = [("NtAlpha", 0x18), ("NtBeta", 0x19), ("NtGamma", 0x1a)];
+
+const FILE_HEADER: usize = 0x84;
+const OPTIONAL_HEADER: usize = 0x98;
+
+fn w16(b: &mut [u8], off: usize, v: u16)
+{
+    b[off..off + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn w32(b: &mut [u8], off: usize, v: u32)
+{
+    b[off..off + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn w64(b: &mut [u8], off: usize, v: u64)
+{
+    b[off..off + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put(b: &mut [u8], off: usize, data: &[u8])
+{
+    b[off..off + data.len()].copy_from_slice(data);
+}
+
+// File offset of an RVA of the synthetic image
+fn raw(rva: usize) -> usize
+{
+    let (_, va, _, ptr, _, _) = SECTIONS.iter().find(|s| rva >= s.1 as usize && rva < (s.1 + 0x1000) as usize).unwrap();
+    rva - *va as usize + *ptr as usize
+}
+
+fn data_directories(bitness: Bitness) -> usize
+{
+    match bitness
+    {
+        Bitness::Bit32 => OPTIONAL_HEADER + 0x60,
+        Bitness::Bit64 => OPTIONAL_HEADER + 0x70,
+    }
+}
+
+fn pe_file(bitness: Bitness) -> Vec<u8>
+{
+    let pe32 = bitness == Bitness::Bit32;
+    let mut f = vec![0u8; 0xc00];
+
+    put(&mut f, 0, b"MZ");
+    w32(&mut f, 0x3c, 0x80);
+    put(&mut f, 0x80, b"PE\0\0");
+
+    // IMAGE_FILE_HEADER
+    let opt_size: u16 = if pe32 { 0xe0 } else { 0xf0 };
+    w16(&mut f, FILE_HEADER, if pe32 { 0x14c } else { 0x8664 });
+    w16(&mut f, FILE_HEADER + 2, SECTIONS.len() as u16);
+    w32(&mut f, FILE_HEADER + 4, 0x5f5e1000);
+    w16(&mut f, FILE_HEADER + 0x10, opt_size);
+    w16(&mut f, FILE_HEADER + 0x12, 0x2022);
+
+    // IMAGE_OPTIONAL_HEADER32 / 64
+    let oh = OPTIONAL_HEADER;
+    w16(&mut f, oh, if pe32 { 0x10b } else { 0x20b });
+    w32(&mut f, oh + 0x10, 0x1010);
+    if pe32
+    {
+        w32(&mut f, oh + 0x1c, BASE32 as u32);
+    }
+    else
+    {
+        w64(&mut f, oh + 0x18, BASE64 as u64);
+    }
+    w32(&mut f, oh + 0x20, 0x1000);
+    w32(&mut f, oh + 0x24, 0x200);
+    w32(&mut f, oh + 0x38, 0x4000);
+    w32(&mut f, oh + 0x3c, 0x400);
+    w16(&mut f, oh + 0x44, 3);
+    w16(&mut f, oh + 0x46, 0x160);
+
+    let dd = data_directories(bitness);
+    w32(&mut f, dd - 4, 16);
+    w32(&mut f, dd, 0x2000);
+    w32(&mut f, dd + 4, 0x100);
+    w32(&mut f, dd + 8, 0x3000);
+    w32(&mut f, dd + 12, 0x3c);
+
+    // Section table
+    for (i, (name, va, vsize, ptr, size, flags)) in SECTIONS.iter().enumerate()
+    {
+        let s = oh + opt_size as usize + i * 40;
+        put(&mut f, s, name);
+        w32(&mut f, s + 8, *vsize);
+        w32(&mut f, s + 12, *va);
+        w32(&mut f, s + 16, *size);
+        w32(&mut f, s + 20, *ptr);
+        w32(&mut f, s + 36, *flags);
+    }
+
+    // Syscall stubs, 0x20 bytes apart
+    for (i, (_, nr)) in SYSCALLS.iter().enumerate()
+    {
+        let nr = nr.to_le_bytes();
+        let stub: Vec<u8> = if pe32
+        {
+            [&[0xb8][..], &nr, &[0xba, 0, 0, 0, 0, 0xff, 0xd2, 0xc2, 0x08, 0x00]].concat()
+        }
+        else
+        {
+            [&[0x4c, 0x8b, 0xd1, 0xb8][..], &nr, &[0x0f, 0x05, 0xc3]].concat()
+        };
+        put(&mut f, raw(0x1000 + i * 0x20), &stub);
+    }
+
+    // IMAGE_EXPORT_DIRECTORY, ordinal base 1
+    let exp = raw(0x2000);
+    w32(&mut f, exp + 0x0c, 0x2100);
+    w32(&mut f, exp + 0x10, 1);
+    w32(&mut f, exp + 0x14, 3);
+    w32(&mut f, exp + 0x18, 3);
+    w32(&mut f, exp + 0x1c, 0x2040);
+    w32(&mut f, exp + 0x20, 0x2050);
+    w32(&mut f, exp + 0x24, 0x2060);
+    put(&mut f, raw(0x2100), b"test.dll\0");
+
+    let mut name = 0x2110;
+    for (i, (fname, _)) in SYSCALLS.iter().enumerate()
+    {
+        w32(&mut f, raw(0x2040) + i * 4, 0x1000 + 0x20 * i as u32);
+        w32(&mut f, raw(0x2050) + i * 4, name as u32);
+        w16(&mut f, raw(0x2060) + i * 2, i as u16);
+        put(&mut f, raw(name), fname.as_bytes());
+        name += fname.len() + 1;
+    }
+
+    // IMAGE_IMPORT_DESCRIPTOR of KERNEL32.dll, lookup table at 0x3080, IAT at 0x30c0
+    let imp = raw(0x3000);
+    w32(&mut f, imp, 0x3080);
+    w32(&mut f, imp + 8, 0xffff_ffff);
+    w32(&mut f, imp + 12, 0x3100);
+    w32(&mut f, imp + 16, 0x30c0);
+    put(&mut f, raw(0x3100), b"KERNEL32.dll\0");
+    put(&mut f, raw(0x3120), b"\x05\x00LoadLibraryA\0");
+
+    let thunks: [u64; 3] = if pe32 { [0x3120, 0x8000_0000 | 42, 0] } else { [0x3120, 0x8000_0000_0000_0000 | 42, 0] };
+    for (i, &t) in thunks.iter().enumerate()
+    {
+        for table in [0x3080, 0x30c0]
+        {
+            let at = raw(table) + i * bitness.ptr_size();
+            if pe32 { w32(&mut f, at, t as u32) } else { w64(&mut f, at, t) }
+        }
+    }
+
+    f
+}
+
+// The file as the loader maps it
+fn pe_mapped(file: &[u8]) -> Vec<u8>
+{
+    let mut m = vec![0u8; 0x4000];
+    m[..0x400].copy_from_slice(&file[..0x400]);
+
+    for (_, va, _, ptr, size, _) in SECTIONS.iter()
+    {
+        let (va, ptr, size) = (*va as usize, *ptr as usize, *size as usize);
+        m[va..va + size].copy_from_slice(&file[ptr..ptr + size]);
+    }
+
+    m
+}
+
+fn preferred_base(bitness: Bitness) -> usize
+{
+    match bitness
+    {
+        Bitness::Bit32 => BASE32,
+        Bitness::Bit64 => BASE64,
+    }
+}
+
+const BITNESSES: [Bitness; 2] = [Bitness::Bit32, Bitness::Bit64];
+
+// =================================================== Layouts
+
+#[test]
+fn file_layout_uses_the_preferred_base()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+
+        assert_eq!(pe.layout(), Layout::File);
+        assert_eq!(pe.bitness(), bitness);
+        assert_eq!(pe.base_addr, preferred_base(bitness));
+        assert_eq!(pe.get_name().unwrap(), "test.dll");
+        assert_eq!(pe.size_of_image(), 0x4000);
+        assert!(pe.export_issues().is_empty());
+    }
+}
+
+#[test]
+fn mapped_and_file_layouts_agree()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let pf = PEImage::from_file_bytes(&file).unwrap();
+        let pm = PEImage::with_source(BufferMemory::new(MAPPED_AT, pe_mapped(&file)), MAPPED_AT, PEName::Empty).unwrap();
+
+        assert_eq!(pm.layout(), Layout::Mapped);
+        assert_eq!(pm.bitness(), bitness);
+        assert_eq!(pm.base_addr, MAPPED_AT);
+        assert_eq!(pm.get_name().unwrap(), "test.dll");
+        assert_eq!(pm.optional_header().unwrap(), pf.optional_header().unwrap());
+
+        for (i, (name, nr)) in SYSCALLS.iter().enumerate()
+        {
+            assert_eq!(pf.syscall_from_name(name).unwrap(), *nr as usize);
+            assert_eq!(pm.syscall_from_name(name).unwrap(), *nr as usize);
+
+            assert_eq!(pf.find_func_addr(name).unwrap(), (pf.base_addr + 0x1000 + 0x20 * i, i + 1));
+            assert_eq!(pm.find_func_addr(name).unwrap(), (MAPPED_AT + 0x1000 + 0x20 * i, i + 1));
+        }
+
+        for s in pf.sections()
+        {
+            assert_eq!(pf.section_bytes(s).unwrap()[..], pm.section_bytes(s).unwrap()[..]);
+        }
+    }
+}
+
+// =================================================== Names
+
+#[test]
+fn long_export_names_are_accepted()
+{
+    let mut file = pe_file(Bitness::Bit64);
+    let long = "?LongDecoratedName@".repeat(30);
+    assert!(long.len() > 0x200);
+
+    // Room for it after the strings of the export directory, up to the end of .rdata raw data
+    put(&mut file, raw(0x2130), long.as_bytes());
+    w32(&mut file, raw(0x2050), 0x2130);
+
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+    assert_eq!(pe.idx_from_name(&long), Some(0));
+}
+
+// =================================================== Cached reads
+
+#[test]
+fn from_file_reads_through_the_cache()
+{
+    let path = std::env::temp_dir().join(format!("nt_utils_test_{}.dll", std::process::id()));
+    let mut file = pe_file(Bitness::Bit64);
+    file.resize(0x3000, 0);
+    std::fs::write(&path, &file).unwrap();
+
+    let pe = PEImage::from_file(&path);
+    let _ = std::fs::remove_file(&path);
+    let pe = pe.unwrap();
+
+    assert_eq!(pe.get_name().unwrap(), "test.dll");
+    assert_eq!(pe.syscall_from_name("NtGamma").unwrap(), 0x1a);
+
+    let stats = pe.source().stats();
+    assert!(stats.hits > stats.misses, "{:?}", stats);
+}
+
+#[test]
+fn export_table_sizes_are_checked_before_reading()
+{
+    for bitness in BITNESSES
+    {
+        let mut file = pe_file(bitness);
+        w32(&mut file, raw(0x2018), 0xffff_ffff);
+        assert!(PEImage::from_file_bytes(&file).err().unwrap().message.contains("cannot hold 4294967295 entries"));
+
+        // The name table fits up to the end of .rdata raw data, the ordinal table does not
+        w32(&mut file, raw(0x2018), 0xe0);
+        w32(&mut file, raw(0x2024), 0x23f0);
+        assert!(PEImage::from_file_bytes(&file).err().unwrap().message.starts_with("Export name ordinals table"));
+    }
+}
+
+// =================================================== Headers
+
+#[test]
+fn optional_header_follows_the_magic()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        let opt = pe.optional_header().unwrap();
+
+        assert_eq!(opt.bitness(), bitness);
+        assert_eq!(opt.image_base() as usize, preferred_base(bitness));
+        assert_eq!(opt.address_of_entry_point(), 0x1010);
+        assert_eq!(opt.data_directory(DataDirectory::Import).unwrap().virtual_address, 0x3000);
+        assert_eq!(pe.file_header().unwrap().machine_type().bitness(), Some(bitness));
+    }
+}
+
+// =================================================== Exports
+
+#[test]
+fn export_lookups_miss_without_panicking()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    assert_eq!(pe.idx_from_name("NtBeta"), Some(1));
+    assert_eq!(pe.idx_from_name("ntbeta"), None);
+    assert_eq!(pe.idx_from_name_ignore_case("ntbeta"), Some(1));
+    assert!(pe.syscall_from_name("NtMissing").is_err());
+    assert!(pe.find_func_addr("NtMissing").is_err());
+    assert!(pe.faddr_from_ord(0).is_err());
+    assert_eq!((&pe).into_iter().count(), 3);
+}
+
+#[test]
+fn syscall_numbers_are_not_truncated()
+{
+    for bitness in BITNESSES
+    {
+        let mut file = pe_file(bitness);
+        let imm = raw(0x1000) + if bitness == Bitness::Bit64 { 4 } else { 1 };
+        w32(&mut file, imm, 0x1234);
+        put(&mut file, raw(0x1020), &[0x90]);
+
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        assert_eq!(pe.syscall_from_name("NtAlpha").unwrap(), 0x1234);
+        assert!(pe.syscall_from_name("NtBeta").is_err());
+    }
+}
+
+// =================================================== Sections
+
+#[test]
+fn section_table()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    let names: Vec<&str> = pe.sections().iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, [".text", ".rdata", ".idata"]);
+    assert_eq!(pe.section_alignment(), 0x1000);
+    assert_eq!(pe.file_alignment(), 0x200);
+
+    let text = pe.section(".text").unwrap();
+    assert!(text.is_code() && text.is_executable() && !text.is_writable());
+    assert!(pe.section(".idata").unwrap().is_writable());
+    assert!(pe.section(".bss").is_none());
+}
+
+#[test]
+fn rva_to_offset_edges()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    // Headers map one to one
+    assert_eq!(pe.rva_to_offset(0).unwrap(), 0);
+    assert_eq!(pe.rva_to_offset(0x3ff).unwrap(), 0x3ff);
+
+    // First and last byte of raw data
+    assert_eq!(pe.rva_to_offset(0x2000).unwrap(), 0x600);
+    assert_eq!(pe.rva_to_offset(0x23ff).unwrap(), 0x9ff);
+
+    // Past the raw data, up to SectionAlignment: mapped but zero filled
+    assert_eq!(pe.section_for_rva(0x2400).unwrap().name, ".rdata");
+    assert_eq!(pe.section_for_rva(0x2fff).unwrap().name, ".rdata");
+    assert!(pe.rva_to_offset(0x2400).is_err());
+
+    // Between the headers and the first section, past the last section
+    assert!(pe.section_for_rva(0x800).is_none());
+    assert!(pe.rva_to_offset(0x800).is_err());
+    assert!(pe.section_for_rva(0x4000).is_none());
+    assert!(pe.rva_to_offset(0x4000).is_err());
+}
+
+#[test]
+fn offset_to_rva_edges()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    assert_eq!(pe.offset_to_rva(0x3c).unwrap(), 0x3c);
+    assert_eq!(pe.offset_to_rva(0x400).unwrap(), 0x1000);
+    assert_eq!(pe.offset_to_rva(0x9ff).unwrap(), 0x23ff);
+    assert_eq!(pe.offset_to_rva(0xa00).unwrap(), 0x3000);
+    assert!(pe.offset_to_rva(0xc00).is_err());
+
+    for rva in [0x0, 0x1010, 0x2000, 0x2123, 0x3100]
+    {
+        assert_eq!(pe.offset_to_rva(pe.rva_to_offset(rva).unwrap()).unwrap(), rva);
+    }
+}
+
+#[test]
+fn va_to_rva_edges()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    assert_eq!(pe.va_to_rva(BASE64).unwrap(), 0);
+    assert_eq!(pe.va_to_rva(BASE64 + 0x3fff).unwrap(), 0x3fff);
+    assert!(pe.va_to_rva(BASE64 + 0x4000).is_err());
+    assert!(pe.va_to_rva(BASE64 - 1).is_err());
+}
+
+#[test]
+fn section_bytes_are_zero_filled_up_to_the_virtual_size()
+{
+    let file = pe_file(Bitness::Bit64);
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+
+    // .idata: 0x200 bytes of raw data, 0x280 once mapped
+    let idata = pe.section(".idata").unwrap();
+    let bytes = pe.section_bytes(idata).unwrap();
+    assert_eq!(bytes.len(), 0x280);
+    assert_eq!(bytes[..0x200], file[0xa00..0xc00]);
+    assert!(bytes[0x200..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn loader_rounding_of_raw_pointers_and_sizes()
+{
+    let mut file = pe_file(Bitness::Bit64);
+    let text_header = OPTIONAL_HEADER + 0xf0;
+
+    // PointerToRawData is rounded down to 0x200, a null VirtualSize means SizeOfRawData
+    w32(&mut file, text_header + 20, 0x410);
+    w32(&mut file, text_header + 8, 0);
+
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+    let text = pe.section(".text").unwrap();
+
+    assert_eq!(text.virtual_extent(), 0x200);
+    assert_eq!(pe.rva_to_offset(0x1000).unwrap(), 0x400);
+    assert_eq!(pe.syscall_from_name("NtAlpha").unwrap(), 0x18);
+}
+
+#[test]
+fn sections_must_fit_in_the_image()
+{
+    for (bitness, opt_size) in [(Bitness::Bit32, 0xe0), (Bitness::Bit64, 0xf0)]
+    {
+        let text_header = OPTIONAL_HEADER + opt_size;
+
+        // A huge VirtualSize would zero fill gigabytes when reading the section from a file
+        let mut file = pe_file(bitness);
+        w32(&mut file, text_header + 8, 0xffff_ff00);
+        assert!(load_error(&file).contains(".text"));
+
+        // One byte past SizeOfImage, only once rounded up to SectionAlignment
+        let mut file = pe_file(bitness);
+        w32(&mut file, text_header + 2 * 40 + 8, 0x1001);
+        assert!(load_error(&file).contains(".idata"));
+    }
+}
+
+// =================================================== Validation
+
+fn load_error(file: &[u8]) -> String
+{
+    PEImage::from_file_bytes(file).expect_err("The image should have been rejected").message
+}
+
+#[test]
+fn invalid_headers_are_rejected()
+{
+    for bitness in BITNESSES
+    {
+        let good = pe_file(bitness);
+
+        let mut file = good.clone();
+        put(&mut file, 0, b"ZM");
+        assert!(load_error(&file).starts_with("Invalid DOS signature"));
+
+        let mut file = good.clone();
+        put(&mut file, 0x80, b"NE");
+        assert!(load_error(&file).starts_with("Invalid NT signature"));
+
+        let mut file = good.clone();
+        w32(&mut file, 0x3c, 0xfff0);
+        assert!(load_error(&file).contains("e_lfanew"));
+
+        let mut file = good.clone();
+        w16(&mut file, FILE_HEADER, 0x1234);
+        assert!(load_error(&file).starts_with("Unsupported machine"));
+
+        let mut file = good.clone();
+        w16(&mut file, FILE_HEADER, if bitness == Bitness::Bit64 { 0x14c } else { 0xaa64 });
+        assert!(load_error(&file).contains("does not match"));
+
+        let mut file = good.clone();
+        w16(&mut file, OPTIONAL_HEADER, 0x107);
+        assert!(load_error(&file).contains("magic"));
+
+        let mut file = good.clone();
+        w16(&mut file, FILE_HEADER + 0x10, 0x20);
+        assert!(load_error(&file).starts_with("SizeOfOptionalHeader"));
+
+        let mut file = good.clone();
+        w32(&mut file, OPTIONAL_HEADER + 0x3c, 0x100);
+        assert!(load_error(&file).contains("does not cover the section table"));
+
+        let mut file = good.clone();
+        w32(&mut file, data_directories(bitness) + 12, 0x2000);
+        assert!(load_error(&file).starts_with("Data directory 1"));
+
+        assert!(PEImage::from_file_bytes(&good[..0x40]).is_err());
+    }
+}
+
+#[test]
+fn bad_export_names_only_invalidate_their_entry()
+{
+    let mut file = pe_file(Bitness::Bit64);
+
+    // Name of NtBeta past the raw data of .rdata, DLL name with invalid UTF-8
+    w32(&mut file, raw(0x2054), 0x2800);
+    put(&mut file, raw(0x2100), b"\xfftest.dll");
+
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+    assert_eq!(pe.export_issues().len(), 1);
+    assert_eq!(pe.idx_from_name("NtGamma"), Some(2));
+    assert_eq!(pe.syscall_from_name("NtGamma").unwrap(), 0x1a);
+    assert_eq!(pe.get_name().unwrap(), "\u{fffd}test.dll");
+}
+
+#[test]
+fn images_without_exports()
+{
+    let mut file = pe_file(Bitness::Bit32);
+    let dd = data_directories(Bitness::Bit32);
+    w64(&mut file, dd, 0);
+
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+    assert!(!pe.has_exports());
+    assert!(pe.export_directory().is_err());
+    assert!(pe.number_of_names().is_err());
+    assert_eq!(pe.get_name().unwrap(), "Unnamed PE");
+    assert_eq!(pe.imports().unwrap().dlls.len(), 1);
+}
+
+// =================================================== Imports
+
+#[test]
+fn imports_by_name_and_by_ordinal()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        let imports = pe.imports().unwrap();
+
+        assert_eq!(imports.dlls.len(), 1);
+        assert!(imports.issues.is_empty());
+        let dll = imports.dll("kernel32.dll").unwrap();
+        assert_eq!(dll.name, "KERNEL32.dll");
+        assert_eq!(dll.time_date_stamp, 0);
+        assert_eq!(dll.forwarder_chain, 0xffff_ffff);
+        assert!(!dll.is_bound());
+
+        assert_eq!(dll.functions.len(), 2);
+        assert_eq!(dll.functions[0].thunk, ImportThunk::ByName { hint: 5, name: String::from("LoadLibraryA") });
+        assert_eq!(dll.functions[0].iat_rva, 0x30c0);
+        assert_eq!(dll.functions[1].thunk, ImportThunk::ByOrdinal(42));
+        assert_eq!(dll.functions[1].iat_rva, 0x30c0 + bitness.ptr_size());
+        assert!(dll.functions.iter().all(|f| f.resolved.is_none()));
+        assert_eq!(dll.function("LoadLibraryA").unwrap().thunk.to_string(), "LoadLibraryA (hint 5)");
+    }
+}
+
+#[test]
+fn mapped_imports_report_the_resolved_iat()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let mut mem = BufferMemory::new(MAPPED_AT, pe_mapped(&file));
+        let size = bitness.ptr_size();
+        mem.write_bytes(MAPPED_AT + 0x30c0, &0x7ffd_1234usize.to_le_bytes()[..size]).unwrap();
+        mem.write_bytes(MAPPED_AT + 0x30c0 + size, &0x7ffd_5678usize.to_le_bytes()[..size]).unwrap();
+
+        let pe = PEImage::with_source(mem, MAPPED_AT, PEName::Empty).unwrap();
+        let dll = &pe.imports().unwrap().dlls[0];
+
+        assert_eq!(dll.functions[0].thunk.name(), Some("LoadLibraryA"));
+        assert_eq!(dll.functions[0].resolved, Some(0x7ffd_1234));
+        assert_eq!(dll.functions[1].thunk.ordinal(), Some(42));
+        assert_eq!(dll.functions[1].resolved, Some(0x7ffd_5678));
+    }
+}
+
+#[test]
+fn filled_iat_without_lookup_table_is_not_decoded()
+{
+    for bitness in BITNESSES
+    {
+        let file = pe_file(bitness);
+        let mut mem = BufferMemory::new(MAPPED_AT, pe_mapped(&file));
+        let size = bitness.ptr_size();
+
+        // No OriginalFirstThunk, and an IAT already filled by the loader
+        mem.write_bytes(MAPPED_AT + 0x3000, &[0; 4]).unwrap();
+        mem.write_bytes(MAPPED_AT + 0x30c0, &0x1234_5678usize.to_le_bytes()[..size]).unwrap();
+
+        let pe = PEImage::with_source(mem, MAPPED_AT, PEName::Empty).unwrap();
+        let dll = &pe.imports().unwrap().dlls[0];
+
+        assert_eq!(dll.functions.len(), 2);
+        assert!(dll.functions.iter().all(|f| f.thunk == ImportThunk::Unknown));
+        assert_eq!(dll.functions[0].resolved, Some(0x1234_5678));
+    }
+}
+
+#[test]
+fn unbound_iat_without_lookup_table_is_decoded_from_the_file()
+{
+    let mut file = pe_file(Bitness::Bit64);
+    w32(&mut file, raw(0x3000), 0);
+
+    let pe = PEImage::from_file_bytes(&file).unwrap();
+    let dll = &pe.imports().unwrap().dlls[0];
+
+    assert_eq!(dll.functions[0].thunk.name(), Some("LoadLibraryA"));
+    assert_eq!(dll.functions[1].thunk.ordinal(), Some(42));
+}
+
+#[test]
+fn bad_import_descriptors_are_skipped()
+{
+    for bitness in BITNESSES
+    {
+        let mut file = pe_file(bitness);
+        let imp = raw(0x3000);
+        let descriptor = file[imp..imp + 20].to_vec();
+
+        // Descriptor #1 names a DLL outside of the image, #2 is USER32.dll with the same thunks
+        put(&mut file, imp + 20, &descriptor);
+        w32(&mut file, imp + 20 + 12, 0x5000);
+        put(&mut file, imp + 40, &descriptor);
+        w32(&mut file, imp + 40 + 12, 0x3140);
+        put(&mut file, raw(0x3140), b"USER32.dll\0");
+
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        let imports = pe.imports().unwrap();
+
+        assert_eq!(imports.dlls.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(), ["KERNEL32.dll", "USER32.dll"]);
+        assert_eq!(imports.dll("user32.dll").unwrap().functions.len(), 2);
+        assert_eq!(imports.issues.len(), 1);
+        assert!(imports.issues[0].starts_with("Import descriptor #1 name"));
+    }
+}
+
+#[test]
+fn imports_without_terminator_keep_the_parsed_dlls()
+{
+    for bitness in BITNESSES
+    {
+        let mut file = pe_file(bitness);
+        let imp = raw(0x3000);
+        let descriptor = file[imp..imp + 20].to_vec();
+
+        // The only descriptor fills the end of the raw data of .idata, nothing follows it
+        put(&mut file, raw(0x31ec), &descriptor);
+        w32(&mut file, data_directories(bitness) + 8, 0x31ec);
+
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        let imports = pe.imports().unwrap();
+
+        assert_eq!(imports.dlls.len(), 1);
+        assert_eq!(imports.dlls[0].functions.len(), 2);
+        assert_eq!(imports.issues.len(), 1);
+        assert!(imports.issues[0].starts_with("Import descriptor #1:"));
+    }
+}
+
+#[test]
+fn bad_import_thunks_end_the_function_list()
+{
+    for bitness in BITNESSES
+    {
+        let mut file = pe_file(bitness);
+
+        // The second lookup entry is an IMAGE_IMPORT_BY_NAME outside of the image
+        let at = raw(0x3080) + bitness.ptr_size();
+        if bitness == Bitness::Bit32 { w32(&mut file, at, 0x5000) } else { w64(&mut file, at, 0x5000) }
+
+        let pe = PEImage::from_file_bytes(&file).unwrap();
+        let imports = pe.imports().unwrap();
+
+        assert_eq!(imports.dlls.len(), 1);
+        assert_eq!(imports.dlls[0].functions.len(), 1);
+        assert_eq!(imports.dlls[0].functions[0].thunk.name(), Some("LoadLibraryA"));
+        assert_eq!(imports.issues.len(), 1);
+        assert!(imports.issues[0].starts_with("Import thunk #1 of KERNEL32.dll"));
+    }
+}